@@ -17,9 +17,12 @@ use std::slice;
 ///
 /// It offers a simple API but also dereferences to a slice, so that the full slice API is
 /// available. The ArrayVec can be converted into a by value iterator.
+///
+/// With the `paranoid` feature, the length is tracked and checked even in release builds,
+/// and slots are overwritten with [`POISON`] as soon as they are vacated.
 #[repr(C)]
 pub struct DetachedArrayVec<T, const CAP: usize> {
-    #[cfg(debug_assertions)]
+    #[cfg(any(debug_assertions, feature = "paranoid"))]
     len: usize,
 
     // the `len` first elements of the array are initialized
@@ -28,7 +31,7 @@ pub struct DetachedArrayVec<T, const CAP: usize> {
 
 macro_rules! debug_assert_eq {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             assert_eq!($($arg)*);
         }
@@ -37,13 +40,16 @@ macro_rules! debug_assert_eq {
 
 macro_rules! debug_assert {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             assert!($($arg)*);
         }
     };
 }
 
+/// The byte written over vacated slots by the `paranoid` feature.
+pub const POISON: u8 = 0xa5;
+
 macro_rules! panic_oob {
     ($method_name:expr, $index:expr, $len:expr) => {
         panic!(
@@ -70,7 +76,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         // assert_capacity_limit!(CAP);
         unsafe {
             DetachedArrayVec {
-                #[cfg(debug_assertions)]
+                #[cfg(any(debug_assertions, feature = "paranoid"))]
                 len: 0,
                 xs: MaybeUninit::uninit().assume_init(),
             }
@@ -91,7 +97,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
     /// arrayvec is full.
     #[track_caller]
     pub unsafe fn insert(&mut self, len: usize, index: usize, element: T) {
-        if cfg!(debug_assertions) {
+        if cfg!(any(debug_assertions, feature = "paranoid")) {
             if index > len {
                 panic_oob!("try_insert", index, len)
             }
@@ -100,7 +106,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
             }
         }
 
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             assert_eq!(len, self.len);
             self.len += 1;
@@ -132,7 +138,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         debug_assert!(index < len);
         debug_assert!(len <= CAP);
 
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             assert_eq!(len, self.len);
             self.len -= 1;
@@ -149,6 +155,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         // the range index..(len-1)
         unsafe {
             ptr::copy(this.add(index + 1), this.add(index), to_shift);
+            self.poison(len - 1, len);
         }
 
         elem
@@ -188,7 +195,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
     // }
 
     // unsafe fn drain_range(&mut self, len: usize, start: usize, end: usize) -> Drain<T, CAP> {
-    //     if cfg!(debug_assertions) {
+    //     if cfg!(any(debug_assertions, feature = "paranoid")) {
     //         if start > end {
     //             panic_oob!("drain", start, end)
     //         }
@@ -219,7 +226,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         let mut other = Self::new();
 
         debug_assert_eq!(self.len, len);
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             self.len = at;
             other.len = other_len;
@@ -227,6 +234,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
 
         unsafe {
             ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_mut_ptr(), other_len);
+            self.poison(at, len);
         }
         other
     }
@@ -255,6 +263,13 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
     }
 
     pub unsafe fn get_ptr_mut(this: *mut Self, index: usize) -> *mut T {
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
+        {
+            // SAFETY: the caller guarantees this points to a live arrayvec.
+            let len = unsafe { *ptr::addr_of!((*this).len) };
+            assert!(index < len, "read of vacated slot {index}, len is {len}");
+        }
+
         unsafe {
             addr_of_mut!((*this).xs)
                 .cast::<MaybeUninit<T>>()
@@ -271,7 +286,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         debug_assert_eq!(self.len, len);
         debug_assert!(len < Self::CAPACITY);
 
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             self.len += 1;
         }
@@ -281,18 +296,44 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
         }
     }
 
+    /// Move all elements of `other` onto the end of this vector.
+    ///
+    /// # Safety
+    /// * `len` elements of self and `other_len` elements of `other` must be init.
+    /// * `len + other_len <= CAP`
+    pub unsafe fn append(&mut self, len: usize, other: Self, other_len: usize) {
+        debug_assert_eq!(self.len, len);
+        debug_assert_eq!(other.len, other_len);
+        debug_assert!(len + other_len <= Self::CAPACITY);
+
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
+        {
+            self.len += other_len;
+        }
+
+        // SAFETY: other owns `other_len` init elements, which fit after our `len` elements.
+        // other is consumed so the elements are not duplicated.
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(), self.as_mut_ptr().add(len), other_len);
+        }
+    }
+
     pub unsafe fn pop(&mut self, len: usize) -> T {
         debug_assert_eq!(self.len, len);
         debug_assert!(len <= Self::CAPACITY);
         debug_assert_ne!(len, 0);
 
         let new_len = len - 1;
-        #[cfg(debug_assertions)]
+        #[cfg(any(debug_assertions, feature = "paranoid"))]
         {
             self.len = new_len;
         }
 
-        unsafe { ptr::read(self.as_ptr().add(new_len)) }
+        unsafe {
+            let elem = ptr::read(self.as_ptr().add(new_len));
+            self.poison(new_len, len);
+            elem
+        }
     }
 
     pub unsafe fn clear(&mut self, len: usize) {
@@ -304,7 +345,7 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
 
         unsafe {
             if new_len < old_len {
-                #[cfg(debug_assertions)]
+                #[cfg(any(debug_assertions, feature = "paranoid"))]
                 {
                     self.len = new_len;
                 }
@@ -312,6 +353,25 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
                 let tail =
                     slice::from_raw_parts_mut(self.as_mut_ptr().add(new_len), old_len - new_len);
                 ptr::drop_in_place(tail);
+                self.poison(new_len, old_len);
+            }
+        }
+    }
+
+    /// Overwrite the slots `start..end`, which must not hold live elements, with [`POISON`].
+    ///
+    /// Under Miri the slots are made uninit again instead, which it tracks precisely.
+    #[inline]
+    unsafe fn poison(&mut self, start: usize, end: usize) {
+        if !cfg!(feature = "paranoid") {
+            return;
+        }
+        for slot in &mut self.xs[start..end] {
+            if cfg!(miri) {
+                *slot = MaybeUninit::uninit();
+            } else {
+                // SAFETY: a MaybeUninit<T> may hold any bytes.
+                unsafe { ptr::write_bytes(slot, POISON, 1) };
             }
         }
     }
@@ -327,6 +387,16 @@ impl<T, const CAP: usize> DetachedArrayVec<T, CAP> {
     }
 }
 
+/// Poison a freed arrayvec, so that a dangling reference into a freed node reads the
+/// pattern rather than a stale element.
+#[cfg(feature = "paranoid")]
+impl<T, const CAP: usize> Drop for DetachedArrayVec<T, CAP> {
+    fn drop(&mut self) {
+        // SAFETY: any remaining elements are either moved out or leaked by now.
+        unsafe { self.poison(0, CAP) }
+    }
+}
+
 /// By-value iterator for `ArrayVec`.
 pub struct IntoIter<T, const CAP: usize> {
     index: usize,
@@ -441,3 +511,36 @@ impl<T, const CAP: usize> Drop for IntoIter<T, CAP> {
 //         }
 //     }
 // }
+
+#[cfg(all(test, feature = "paranoid"))]
+mod test {
+    use super::DetachedArrayVec;
+
+    #[test]
+    #[cfg(not(miri))]
+    fn poison_vacated() {
+        use super::POISON;
+
+        let mut v = DetachedArrayVec::<u32, 4>::new();
+        unsafe {
+            v.push(0, 1);
+            v.push(1, 2);
+            v.push(2, 3);
+            assert_eq!(v.remove(3, 0), 1);
+            assert_eq!(v.as_slice(2), [2, 3]);
+            assert_eq!(v.xs[2].assume_init(), u32::from_ne_bytes([POISON; 4]));
+        }
+    }
+
+    #[test]
+    #[should_panic = "read of vacated slot 1"]
+    fn read_vacated() {
+        let mut v = DetachedArrayVec::<u32, 4>::new();
+        unsafe {
+            v.push(0, 1);
+            v.push(1, 2);
+            v.pop(2);
+            DetachedArrayVec::get_ptr_mut(&mut v, 1);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::OkBTree;
+
+/// A batch of insertions performed a few at a time, from [`OkBTree::insert_batch`].
+///
+/// As a [`Future`], each poll inserts up to `budget` values and then yields back to the
+/// executor, so a large batch doesn't hold up other tasks. Outside of async code, call
+/// [`InsertBatch::step`] until it returns `false`.
+#[must_use = "nothing is inserted until the batch is polled or stepped"]
+pub struct InsertBatch<'a, T, I, const M: usize> {
+    tree: &'a mut OkBTree<T, M>,
+    iter: I,
+    budget: usize,
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Insert every value from `iter`, at most `budget` values per step.
+    ///
+    /// # Panics
+    /// Panics if `budget` is zero.
+    pub fn insert_batch<I>(&mut self, iter: I, budget: usize) -> InsertBatch<'_, T, I::IntoIter, M>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        assert!(budget > 0, "insert_batch called with a budget of zero");
+        InsertBatch {
+            tree: self,
+            iter: iter.into_iter(),
+            budget,
+        }
+    }
+}
+
+impl<T: Ord, I: Iterator<Item = T>, const M: usize> InsertBatch<'_, T, I, M> {
+    /// Insert up to `budget` values. Returns whether there may be more to insert.
+    pub fn step(&mut self) -> bool {
+        for _ in 0..self.budget {
+            match self.iter.next() {
+                Some(value) => {
+                    self.tree.insert(value);
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<T: Ord, I: Iterator<Item = T> + Unpin, const M: usize> Future for InsertBatch<'_, T, I, M> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.get_mut().step() {
+            // there is more work to do, but give other tasks a turn first.
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    use crate::OkBTree;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn insert_batch() {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut btree = OkBTree::new();
+        let mut batch = pin!(btree.insert_batch(0..250, 100));
+        let mut polls = 1;
+        while batch.as_mut().poll(&mut cx).is_pending() {
+            polls += 1;
+        }
+        assert_eq!(polls, 3);
+        assert_eq!(btree.first(), Some(&0));
+        assert_eq!(btree.last(), Some(&249));
+
+        let mut batch = btree.insert_batch(250..300, 50);
+        assert!(batch.step());
+        assert!(!batch.step());
+        assert_eq!(btree.last(), Some(&299));
+
+        let mut batch = pin!(btree.insert_batch(None, 1));
+        assert_eq!(batch.as_mut().poll(&mut cx), Poll::Ready(()));
+    }
+}
@@ -0,0 +1,78 @@
+use std::ops::Bound;
+
+/// Keys with an immediate predecessor and successor, for building tight range bounds with
+/// [`before`] and [`after`].
+pub trait Discrete: Sized {
+    /// The smallest key greater than `self`, if there is one.
+    fn next(&self) -> Option<Self>;
+    /// The largest key less than `self`, if there is one.
+    fn prev(&self) -> Option<Self>;
+}
+
+macro_rules! discrete_int {
+    ($($t:ty),*) => {$(
+        impl Discrete for $t {
+            fn next(&self) -> Option<Self> {
+                self.checked_add(1)
+            }
+            fn prev(&self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+        }
+    )*};
+}
+
+discrete_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// An upper bound that admits exactly the keys less than `k`.
+///
+/// This is `Included(k - 1)`, or `Excluded(k)` when `k` is the minimum and nothing is
+/// less than it.
+pub fn before<K: Discrete + Clone>(k: &K) -> Bound<K> {
+    k.prev()
+        .map_or_else(|| Bound::Excluded(k.clone()), Bound::Included)
+}
+
+/// A lower bound that admits exactly the keys greater than `k`.
+///
+/// This is `Included(k + 1)`, or `Excluded(k)` when `k` is the maximum and nothing is
+/// greater than it.
+pub fn after<K: Discrete + Clone>(k: &K) -> Bound<K> {
+    k.next()
+        .map_or_else(|| Bound::Excluded(k.clone()), Bound::Included)
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Bound;
+
+    use super::{after, before};
+    use crate::OkBTree;
+
+    #[test]
+    fn bounds() {
+        assert_eq!(after(&5u8), Bound::Included(6));
+        assert_eq!(after(&u8::MAX), Bound::Excluded(u8::MAX));
+        assert_eq!(before(&5i8), Bound::Included(4));
+        assert_eq!(before(&i8::MIN), Bound::Excluded(i8::MIN));
+
+        let mut btree = OkBTree::<u8>::default();
+        for i in [0, 10, 20, u8::MAX] {
+            btree.insert(i);
+        }
+        assert_eq!(
+            btree.first_in_range((after(&10), Bound::Unbounded)),
+            Some(&20)
+        );
+        assert_eq!(btree.first_in_range((after(&20), before(&u8::MAX))), None);
+        assert_eq!(
+            btree.last_in_range((Bound::Unbounded, before(&10))),
+            Some(&0)
+        );
+        assert_eq!(
+            btree.first_in_range((after(&u8::MAX), Bound::Unbounded)),
+            None
+        );
+        assert_eq!(btree.last_in_range((Bound::Unbounded, before(&0))), None);
+    }
+}
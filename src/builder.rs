@@ -0,0 +1,65 @@
+use crate::{node::NodeArray, DuplicatePolicy, OkBTree};
+
+/// Configures an [`OkBTree`] before it is created.
+///
+/// The fanout is part of the tree's type, so setting it changes the type of the builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OkBTreeBuilder<const M: usize = 8> {
+    policy: DuplicatePolicy,
+}
+
+impl OkBTreeBuilder {
+    /// A builder for a tree with the default fanout and duplicate policy.
+    pub const fn new() -> Self {
+        OkBTreeBuilder {
+            policy: DuplicatePolicy::Overwrite,
+        }
+    }
+}
+
+impl Default for OkBTreeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const M: usize> OkBTreeBuilder<M> {
+    /// Set the maximum number of elements in each node. `N` must be even and greater
+    /// than one, which is checked at compile time.
+    pub const fn fanout<const N: usize>(self) -> OkBTreeBuilder<N> {
+        assert!(NodeArray::<(), N>::__M_IS_GREATER_THAN_ONE);
+        assert!(NodeArray::<(), N>::__M_IS_EVEN);
+        OkBTreeBuilder {
+            policy: self.policy,
+        }
+    }
+
+    /// Set how inserting an element equal to an existing one is handled.
+    pub const fn duplicate_policy(self, policy: DuplicatePolicy) -> Self {
+        OkBTreeBuilder { policy }
+    }
+
+    /// Create an empty tree with this configuration.
+    pub const fn build<T>(self) -> OkBTree<T, M> {
+        OkBTree::with_policy(self.policy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OkBTreeBuilder;
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn builder() {
+        let mut tree: OkBTree<i32, 2> = OkBTreeBuilder::new()
+            .duplicate_policy(DuplicatePolicy::Reject)
+            .fanout::<2>()
+            .build();
+        for i in 0..100 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.insert(5), Some(5));
+        assert_eq!(tree.last(), Some(&99));
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use std::{cell::Cell, time::Duration};
+
+/// A source of monotonic time, for the features that measure or compare times.
+///
+/// Times are offsets from an arbitrary, fixed origin chosen by the clock. Use
+/// [`ManualClock`] in tests and simulations to make these features deterministic.
+pub trait Clock {
+    /// The current time. It must never go backwards.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// The system's monotonic clock, measured from when the `StdClock` was created.
+///
+/// Only available with the `metrics` feature, as reading it panics on targets without a
+/// system clock, such as `wasm32-unknown-unknown`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    origin: Instant,
+}
+
+#[cfg(feature = "metrics")]
+impl Default for StdClock {
+    fn default() -> Self {
+        StdClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(now: Duration) -> Self {
+        ManualClock {
+            now: Cell::new(now),
+        }
+    }
+
+    /// Move the clock forwards by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
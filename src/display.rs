@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::OkBTree;
+
+/// Prints the shape of an [`OkBTree`] one level per line, from [`OkBTree::display_tree`].
+///
+/// Each node is printed as its pivots followed by how full it is, so an underfull or
+/// misplaced node stands out in a failing test. Nodes on the same level are separated by
+/// `|`, and each line starts with its distance from the root.
+pub struct DisplayTree<'a, T, const M: usize> {
+    tree: &'a OkBTree<T, M>,
+}
+
+impl<T, const M: usize> OkBTree<T, M> {
+    /// Format the tree level by level, showing node boundaries and fill counts.
+    pub fn display_tree(&self) -> DisplayTree<'_, T, M> {
+        DisplayTree { tree: self }
+    }
+}
+
+impl<T: fmt::Debug, const M: usize> fmt::Display for DisplayTree<'_, T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(inner) = &self.tree.0 else {
+            return f.write_str("(empty)");
+        };
+
+        let root = inner.depth.get() - 1;
+        let mut height = None;
+        for node in self.tree.iter_nodes_breadth_first() {
+            if height == Some(node.height()) {
+                f.write_str(" |")?;
+            } else {
+                if height.is_some() {
+                    f.write_str("\n")?;
+                }
+                write!(f, "{}:", root - node.height())?;
+                height = Some(node.height());
+            }
+            let pivots = node.pivots();
+            write!(f, " {pivots:?} ({}/{M})", pivots.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::OkBTree;
+
+    #[test]
+    fn display_tree() {
+        let mut btree = OkBTree::<i32, 2>::default();
+        assert_eq!(btree.display_tree().to_string(), "(empty)");
+
+        btree.insert(0);
+        assert_eq!(btree.display_tree().to_string(), "0: [0] (1/2)");
+
+        for i in 1..5 {
+            btree.insert(i);
+        }
+        assert_eq!(
+            btree.display_tree().to_string(),
+            "0: [1, 3] (2/2)\n1: [0] (1/2) | [2] (1/2) | [4] (1/2)"
+        );
+    }
+}
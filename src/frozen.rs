@@ -0,0 +1,195 @@
+use std::{
+    ops::{Bound, RangeBounds},
+    slice,
+};
+
+use equivalent::Comparable;
+
+use crate::NotSorted;
+
+/// A read-only set over a sorted slice, with the same queries as [`OkBTree`](crate::OkBTree).
+///
+/// It can be built in a `const` context, so a lookup table can be declared as a `static`
+/// and placed in read-only memory.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct FrozenBTree<'a, T> {
+    elements: &'a [T],
+}
+
+impl<T> Clone for FrozenBTree<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FrozenBTree<'_, T> {}
+
+impl<'a, T> FrozenBTree<'a, T> {
+    /// Wrap a slice of strictly increasing elements.
+    ///
+    /// The order can't be checked in a `const` context, so it is not checked at all. It is a
+    /// logic error for the elements to not be strictly increasing.
+    pub const fn from_sorted_unchecked(elements: &'a [T]) -> Self {
+        FrozenBTree { elements }
+    }
+
+    /// The elements, in ascending order.
+    pub const fn as_slice(&self) -> &'a [T] {
+        self.elements
+    }
+
+    pub const fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn first(&self) -> Option<&'a T> {
+        self.elements.first()
+    }
+
+    pub fn last(&self) -> Option<&'a T> {
+        self.elements.last()
+    }
+
+    fn search<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Result<usize, usize> {
+        self.elements
+            .binary_search_by(|element| q.compare(element).reverse())
+    }
+
+    pub fn get<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Option<&'a T> {
+        self.get_full(q).map(|(_, element)| element)
+    }
+
+    /// Get the element equal to `q`, along with its rank: the number of smaller elements.
+    pub fn get_full<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Option<(usize, &'a T)> {
+        let index = self.search(q).ok()?;
+        Some((index, &self.elements[index]))
+    }
+
+    pub fn contains<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> bool {
+        self.search(q).is_ok()
+    }
+
+    /// Iterate over the elements in ascending order.
+    pub fn iter(&self) -> slice::Iter<'a, T> {
+        self.elements.iter()
+    }
+
+    /// Iterate over the elements within `range`, in ascending order.
+    ///
+    /// A range whose start is after its end is empty.
+    pub fn range<Q, R>(&self, range: R) -> slice::Iter<'a, T>
+    where
+        Q: Comparable<T> + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(q) => self.partition_point(|e| q.compare(e).is_gt()),
+            Bound::Excluded(q) => self.partition_point(|e| q.compare(e).is_ge()),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(q) => self.partition_point(|e| q.compare(e).is_ge()),
+            Bound::Excluded(q) => self.partition_point(|e| q.compare(e).is_gt()),
+            Bound::Unbounded => self.elements.len(),
+        };
+        self.elements[start..end.max(start)].iter()
+    }
+
+    /// Iterate over the elements not less than `q`, in ascending order.
+    pub fn iter_from<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> slice::Iter<'a, T> {
+        let start = self.partition_point(|e| q.compare(e).is_gt());
+        self.elements[start..].iter()
+    }
+
+    fn partition_point(&self, pred: impl FnMut(&T) -> bool) -> usize {
+        self.elements.partition_point(pred)
+    }
+}
+
+impl<'a, T> IntoIterator for FrozenBTree<'a, T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.elements.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &FrozenBTree<'a, T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.elements.iter()
+    }
+}
+
+impl<'a, T: Ord> FrozenBTree<'a, T> {
+    /// Wrap a slice, checking that its elements are strictly increasing.
+    ///
+    /// If they are not, the position of the first out of order element is returned.
+    pub fn from_sorted(elements: &'a [T]) -> Result<Self, NotSorted> {
+        match elements.windows(2).position(|w| w[0] >= w[1]) {
+            Some(index) => Err(NotSorted { index: index + 1 }),
+            None => Ok(FrozenBTree { elements }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Bound;
+
+    use super::FrozenBTree;
+    use crate::NotSorted;
+
+    static SQUARES: FrozenBTree<'static, u32> =
+        FrozenBTree::from_sorted_unchecked(&[0, 1, 4, 9, 16, 25]);
+
+    #[test]
+    fn frozen() {
+        assert_eq!(SQUARES.len(), 6);
+        assert_eq!(SQUARES.get(&9), Some(&9));
+        assert_eq!(SQUARES.get(&10), None);
+        assert_eq!(SQUARES.get_full(&16), Some((4, &16)));
+        assert_eq!(SQUARES.first(), Some(&0));
+        assert_eq!(SQUARES.last(), Some(&25));
+        assert!(SQUARES.contains(&4));
+        assert!(!SQUARES.contains(&5));
+    }
+
+    #[test]
+    fn iter_range() {
+        assert!(SQUARES.iter().eq(&[0, 1, 4, 9, 16, 25]));
+        assert!(SQUARES.into_iter().rev().eq(&[25, 16, 9, 4, 1, 0]));
+        assert!(SQUARES.range(1..16).eq(&[1, 4, 9]));
+        assert!(SQUARES.range(2..=16).eq(&[4, 9, 16]));
+        assert!(SQUARES
+            .range((Bound::Excluded(4), Bound::Unbounded))
+            .eq(&[9, 16, 25]));
+        assert!(SQUARES.range::<u32, _>(..).eq(SQUARES.iter()));
+        assert_eq!(
+            SQUARES
+                .range((Bound::Included(10), Bound::Excluded(5)))
+                .count(),
+            0
+        );
+        assert_eq!(SQUARES.range(5..5).count(), 0);
+        assert!(SQUARES.iter_from(&5).eq(&[9, 16, 25]));
+        assert!(SQUARES.iter_from(&9).eq(&[9, 16, 25]));
+        assert_eq!(SQUARES.iter_from(&26).count(), 0);
+    }
+
+    #[test]
+    fn from_sorted() {
+        assert!(FrozenBTree::from_sorted(&[1, 2, 3]).is_ok());
+        assert_eq!(
+            FrozenBTree::from_sorted(&[1, 3, 3]),
+            Err(NotSorted { index: 2 })
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use equivalent::Comparable;
+
+use crate::OkBTree;
+
+/// The two copies of the tree, and which one readers are sent to.
+struct Shared<T, const M: usize> {
+    trees: [UnsafeCell<OkBTree<T, M>>; 2],
+    published: AtomicUsize,
+    /// One counter per [`ReadHandle`]. It is odd while the handle is reading.
+    epochs: Mutex<Vec<Arc<AtomicUsize>>>,
+}
+
+// SAFETY: readers only get shared access to the published copy, and the writer only
+// mutates the other copy once every reader has left it.
+unsafe impl<T: Send + Sync, const M: usize> Sync for Shared<T, M> {}
+// SAFETY: the trees are owned, so they can be sent wherever their elements can.
+unsafe impl<T: Send, const M: usize> Send for Shared<T, M> {}
+
+enum Op<T> {
+    Insert(T),
+    Remove(T),
+}
+
+/// The single writer of a tree split with [`OkBTree::into_handles`].
+///
+/// Changes are made to a copy that readers can't see, and only become visible to
+/// [`ReadHandle`]s when [`WriteHandle::publish`] is called. Reading through the
+/// `WriteHandle` itself sees every change, published or not. Changes that haven't been
+/// published when it is dropped are never seen by the readers.
+pub struct WriteHandle<T, const M: usize = 8> {
+    shared: Arc<Shared<T, M>>,
+    /// Changes made to the write copy since the last publish, to replay onto the other.
+    log: Vec<Op<T>>,
+}
+
+/// A reader of a tree split with [`OkBTree::into_handles`].
+///
+/// Reads see the tree as of the last [`WriteHandle::publish`], and never wait for the
+/// writer. Clone the handle to read from another thread.
+pub struct ReadHandle<T, const M: usize = 8> {
+    shared: Arc<Shared<T, M>>,
+    epoch: Arc<AtomicUsize>,
+    /// The number of live guards from this handle. Only the outermost one moves `epoch`.
+    guards: Cell<usize>,
+}
+
+/// Shared access to the published tree, from [`ReadHandle::read`].
+///
+/// The writer can't publish twice while this is held, so it should not be kept for long.
+pub struct ReadGuard<'a, T, const M: usize = 8> {
+    handle: &'a ReadHandle<T, M>,
+    tree: &'a OkBTree<T, M>,
+}
+
+impl<T: Clone, const M: usize> OkBTree<T, M> {
+    /// Split the tree into a single writer and any number of wait-free readers.
+    ///
+    /// The tree is cloned once, and the two copies take turns being read and written.
+    /// Each change is applied to both copies: once when it is made, and again after the
+    /// copies are swapped on [`WriteHandle::publish`]. This doubles the memory used and
+    /// the cost of each write, in exchange for reads that never block.
+    pub fn into_handles(self) -> (WriteHandle<T, M>, ReadHandle<T, M>) {
+        let shared = Arc::new(Shared {
+            trees: [UnsafeCell::new(self.clone()), UnsafeCell::new(self)],
+            published: AtomicUsize::new(0),
+            epochs: Mutex::new(Vec::new()),
+        });
+        let read = ReadHandle::new(shared.clone());
+        let write = WriteHandle {
+            shared,
+            log: Vec::new(),
+        };
+        (write, read)
+    }
+}
+
+impl<T: Ord + Clone, const M: usize> WriteHandle<T, M> {
+    fn write_tree(&mut self) -> &mut OkBTree<T, M> {
+        let index = 1 - self.shared.published.load(Ordering::SeqCst);
+        // SAFETY: readers are only sent to the published copy, and `publish` waits for
+        // every reader to leave this one before it returns.
+        unsafe { &mut *self.shared.trees[index].get() }
+    }
+
+    /// Insert `value`, as [`OkBTree::insert`] does. Readers see it after the next publish.
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let old = self.write_tree().insert(value.clone());
+        self.log.push(Op::Insert(value));
+        old
+    }
+
+    /// Remove the element equal to `q`, as [`OkBTree::remove`] does. Readers stop seeing
+    /// it after the next publish.
+    pub fn remove<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<T> {
+        let removed = self.write_tree().remove(q)?;
+        self.log.push(Op::Remove(removed.clone()));
+        Some(removed)
+    }
+
+    /// The number of changes that readers can't see yet.
+    pub fn pending(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Make every change so far visible to the readers.
+    ///
+    /// The copies are swapped, so new reads see the changes straight away. This then
+    /// waits for reads still in progress on the old copy to finish, and replays the
+    /// changes onto it so it can be written to next.
+    pub fn publish(&mut self) {
+        if self.log.is_empty() {
+            return;
+        }
+        let shared = &*self.shared;
+        let old = shared.published.load(Ordering::SeqCst);
+        shared.published.store(1 - old, Ordering::SeqCst);
+
+        // a reader that entered before the swap has an odd epoch until it leaves. One
+        // that enters after it sees the new copy.
+        let epochs = shared.epochs.lock().unwrap_or_else(|e| e.into_inner());
+        for epoch in epochs.iter() {
+            let start = epoch.load(Ordering::SeqCst);
+            if start % 2 == 1 {
+                while epoch.load(Ordering::SeqCst) == start {
+                    thread::yield_now();
+                }
+            }
+        }
+        drop(epochs);
+
+        // SAFETY: no reader is on the old copy anymore, and new ones go to the other.
+        let tree = unsafe { &mut *shared.trees[old].get() };
+        for op in self.log.drain(..) {
+            match op {
+                Op::Insert(value) => {
+                    tree.insert(value);
+                }
+                Op::Remove(value) => {
+                    tree.remove(&value);
+                }
+            }
+        }
+    }
+
+    /// Another reader of the published tree.
+    pub fn reader(&self) -> ReadHandle<T, M> {
+        ReadHandle::new(self.shared.clone())
+    }
+}
+
+impl<T, const M: usize> Deref for WriteHandle<T, M> {
+    type Target = OkBTree<T, M>;
+
+    /// The tree with every change made so far, including unpublished ones.
+    fn deref(&self) -> &OkBTree<T, M> {
+        let index = 1 - self.shared.published.load(Ordering::SeqCst);
+        // SAFETY: only this handle writes to the unpublished copy, and it can't while
+        // this borrow is held.
+        unsafe { &*self.shared.trees[index].get() }
+    }
+}
+
+impl<T, const M: usize> ReadHandle<T, M> {
+    fn new(shared: Arc<Shared<T, M>>) -> Self {
+        let epoch = Arc::new(AtomicUsize::new(0));
+        shared
+            .epochs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(epoch.clone());
+        ReadHandle {
+            shared,
+            epoch,
+            guards: Cell::new(0),
+        }
+    }
+
+    /// Borrow the tree as of the last publish.
+    pub fn read(&self) -> ReadGuard<'_, T, M> {
+        let guards = self.guards.get();
+        if guards == 0 {
+            self.epoch.fetch_add(1, Ordering::SeqCst);
+        }
+        self.guards.set(guards + 1);
+
+        let index = self.shared.published.load(Ordering::SeqCst);
+        // SAFETY: our epoch is odd, so the writer won't touch this copy until the guard
+        // is dropped.
+        let tree = unsafe { &*self.shared.trees[index].get() };
+        ReadGuard { handle: self, tree }
+    }
+}
+
+impl<T, const M: usize> Clone for ReadHandle<T, M> {
+    fn clone(&self) -> Self {
+        ReadHandle::new(self.shared.clone())
+    }
+}
+
+impl<T, const M: usize> Drop for ReadHandle<T, M> {
+    fn drop(&mut self) {
+        let mut epochs = self.shared.epochs.lock().unwrap_or_else(|e| e.into_inner());
+        epochs.retain(|epoch| !Arc::ptr_eq(epoch, &self.epoch));
+    }
+}
+
+impl<T, const M: usize> Deref for ReadGuard<'_, T, M> {
+    type Target = OkBTree<T, M>;
+
+    fn deref(&self) -> &OkBTree<T, M> {
+        self.tree
+    }
+}
+
+impl<T, const M: usize> Drop for ReadGuard<'_, T, M> {
+    fn drop(&mut self) {
+        let guards = self.handle.guards.get() - 1;
+        self.handle.guards.set(guards);
+        if guards == 0 {
+            self.handle.epoch.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::OkBTree;
+
+    #[test]
+    fn publish() {
+        let (mut write, read) = OkBTree::<i32, 2>::from_iter(0..10).into_handles();
+        write.insert(10);
+        write.remove(&0);
+        assert_eq!(write.pending(), 2);
+        assert_eq!(write.len(), 10);
+        assert_eq!(write.first(), Some(&1));
+
+        {
+            let tree = read.read();
+            // nested guards from the same handle
+            let again = read.read();
+            assert!(tree.iter().eq(&(0..10).collect::<Vec<_>>()));
+            assert_eq!(again.len(), 10);
+        }
+
+        write.publish();
+        assert_eq!(write.pending(), 0);
+        assert!(read.read().iter().eq(&(1..11).collect::<Vec<_>>()));
+
+        // both copies are in step after a publish
+        write.insert(11);
+        write.publish();
+        write.insert(12);
+        assert!(read.read().iter().eq(&(1..12).collect::<Vec<_>>()));
+        assert!(write.iter().eq(&(1..13).collect::<Vec<_>>()));
+        write.validate().unwrap();
+        read.read().validate().unwrap();
+    }
+
+    #[test]
+    fn concurrent_readers() {
+        let (mut write, read) = OkBTree::<usize, 2>::default().into_handles();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let read = read.clone();
+                s.spawn(move || {
+                    let mut last = 0;
+                    while last < 200 {
+                        let tree = read.read();
+                        // every publish adds the next element, so a reader sees a prefix
+                        assert!(tree.len() >= last);
+                        assert_eq!(tree.last().map_or(0, |&max| max + 1), tree.len());
+                        last = tree.len();
+                    }
+                });
+            }
+            for i in 0..200 {
+                write.insert(i);
+                write.publish();
+            }
+        });
+        drop(read);
+        assert_eq!(write.len(), 200);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::OkBTree;
+
+/// A contiguous run of elements in an equi-depth histogram, from [`OkBTree::histogram`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bucket<'a, T> {
+    /// The smallest element in the bucket.
+    pub first: &'a T,
+    /// The largest element in the bucket.
+    pub last: &'a T,
+    /// How many elements the bucket holds.
+    pub count: usize,
+}
+
+impl<T> Clone for Bucket<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Bucket<'_, T> {}
+
+/// Two consecutive elements and the distance between them, from [`OkBTree::largest_gap`]
+/// and [`OkBTree::gaps_over`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Gap<'a, T, D> {
+    /// The element before the gap.
+    pub lo: &'a T,
+    /// The element after the gap.
+    pub hi: &'a T,
+    /// The distance between `lo` and `hi`.
+    pub distance: D,
+}
+
+impl<T, D: Clone> Clone for Gap<'_, T, D> {
+    fn clone(&self) -> Self {
+        Gap {
+            lo: self.lo,
+            hi: self.hi,
+            distance: self.distance.clone(),
+        }
+    }
+}
+
+impl<T, D: Copy> Copy for Gap<'_, T, D> {}
+
+impl<T, const M: usize> OkBTree<T, M> {
+    fn for_each<'a>(&'a self, mut f: impl FnMut(&'a T)) {
+        if let Some(inner) = &self.0 {
+            inner.node.for_each(inner.depth.get() - 1, &mut f);
+        }
+    }
+
+    /// Split the tree into `buckets` contiguous runs of elements whose sizes differ by at
+    /// most one.
+    ///
+    /// Fewer buckets are returned if the tree holds fewer than `buckets` elements.
+    /// Nodes don't track the size of their subtrees, so the bucket boundaries can't be
+    /// selected by rank in `O(log n)` each. Instead this walks the whole tree, in `O(n)`.
+    pub fn histogram(&self, buckets: usize) -> Vec<Bucket<'_, T>> {
+        let len = self.len();
+        let buckets = buckets.min(len);
+        let mut histogram: Vec<Bucket<'_, T>> = Vec::with_capacity(buckets);
+        if buckets == 0 {
+            return histogram;
+        }
+
+        let mut index = 0;
+        self.for_each(|value| {
+            // spread the elements evenly: the ith goes in bucket `i * buckets / len`.
+            let bucket = index * buckets / len;
+            index += 1;
+
+            match histogram.get_mut(bucket) {
+                Some(b) => {
+                    b.last = value;
+                    b.count += 1;
+                }
+                None => histogram.push(Bucket {
+                    first: value,
+                    last: value,
+                    count: 1,
+                }),
+            }
+        });
+        histogram
+    }
+
+    /// The `n - 1` elements that split the tree into `n` contiguous shards of near-equal size.
+    ///
+    /// Shard `i` holds the elements from `points[i - 1]` (inclusive) up to `points[i]`
+    /// (exclusive), with the first and last shards unbounded below and above.
+    /// Fewer points are returned if the tree holds fewer than `n` elements.
+    /// The points come from [`OkBTree::histogram`], so this also walks the whole tree.
+    pub fn partition_points(&self, n: usize) -> Vec<&T> {
+        self.histogram(n).iter().skip(1).map(|b| b.first).collect()
+    }
+
+    /// Call `f` with every pair of consecutive elements, in order.
+    fn for_each_gap<'a>(&'a self, mut f: impl FnMut(&'a T, &'a T)) {
+        let mut prev = None;
+        self.for_each(|value| {
+            if let Some(prev) = prev {
+                f(prev, value);
+            }
+            prev = Some(value);
+        });
+    }
+
+    /// The widest gap between two consecutive elements, as measured by `distance`.
+    ///
+    /// If several gaps are equally wide, the first is returned. Nodes don't track any
+    /// gap information, so this walks the whole tree.
+    pub fn largest_gap<D: PartialOrd>(
+        &self,
+        mut distance: impl FnMut(&T, &T) -> D,
+    ) -> Option<Gap<'_, T, D>> {
+        let mut largest: Option<Gap<'_, T, D>> = None;
+        self.for_each_gap(|lo, hi| {
+            let distance = distance(lo, hi);
+            if largest.as_ref().map_or(true, |g| distance > g.distance) {
+                largest = Some(Gap { lo, hi, distance });
+            }
+        });
+        largest
+    }
+
+    /// Every gap between two consecutive elements that is wider than `threshold`, as
+    /// measured by `distance`, in order.
+    pub fn gaps_over<D: PartialOrd>(
+        &self,
+        threshold: D,
+        mut distance: impl FnMut(&T, &T) -> D,
+    ) -> Vec<Gap<'_, T, D>> {
+        let mut gaps = Vec::new();
+        self.for_each_gap(|lo, hi| {
+            let distance = distance(lo, hi);
+            if distance > threshold {
+                gaps.push(Gap { lo, hi, distance });
+            }
+        });
+        gaps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Gap;
+    use crate::OkBTree;
+
+    #[test]
+    fn histogram() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+
+        let histogram = btree.histogram(3);
+        let bounds: Vec<_> = histogram
+            .iter()
+            .map(|b| (*b.first, *b.last, b.count))
+            .collect();
+        assert_eq!(bounds, [(0, 333, 334), (334, 666, 333), (667, 999, 333)]);
+
+        assert_eq!(btree.histogram(2000).len(), 1000);
+        assert!(btree.histogram(0).is_empty());
+        assert!(OkBTree::<i32>::new().histogram(4).is_empty());
+    }
+
+    #[test]
+    fn partition_points() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+
+        assert_eq!(btree.partition_points(4), [&250, &500, &750]);
+        assert!(btree.partition_points(1).is_empty());
+        assert_eq!(btree.partition_points(5000).len(), 999);
+    }
+
+    #[test]
+    fn gaps() {
+        let mut btree = OkBTree::new();
+        for i in [1, 2, 3, 10, 11, 30, 31, 32, 50] {
+            btree.insert(i);
+        }
+        let distance = |lo: &i32, hi: &i32| hi - lo;
+
+        assert_eq!(
+            btree.largest_gap(distance),
+            Some(Gap {
+                lo: &11,
+                hi: &30,
+                distance: 19
+            })
+        );
+        let gaps: Vec<_> = btree
+            .gaps_over(5, distance)
+            .iter()
+            .map(|g| (*g.lo, *g.hi))
+            .collect();
+        assert_eq!(gaps, [(3, 10), (11, 30), (32, 50)]);
+
+        btree.remove(&50);
+        btree.remove(&32);
+        assert_eq!(btree.largest_gap(distance).map(|g| g.distance), Some(19));
+        assert!(OkBTree::<i32>::new().largest_gap(distance).is_none());
+    }
+}
@@ -0,0 +1,962 @@
+use std::{
+    collections::VecDeque,
+    hint::black_box,
+    iter::FusedIterator,
+    mem::{self, MaybeUninit},
+    ops::{Bound, RangeBounds},
+    ptr,
+};
+
+use equivalent::Comparable;
+
+use crate::{
+    node::{NodeArray, OwnedNode},
+    NodeRef, OkBTree,
+};
+
+/// The front end of an iteration: the path from the root to the next element to yield,
+/// with the index of that element's pivot in each node.
+///
+/// Frames with no pivots left to yield are popped eagerly, so the top of the stack always
+/// holds the next element, and the stack is empty once the end of the tree is reached.
+struct Front<'a, T, const M: usize> {
+    stack: Vec<(NodeRef<'a, T, M>, usize)>,
+}
+
+impl<'a, T, const M: usize> Front<'a, T, M> {
+    /// Seek to the first element of the subtree at `node` for which `before` returns false.
+    /// `before` must be true for a prefix of the elements.
+    fn seek(mut node: NodeRef<'a, T, M>, mut before: impl FnMut(&T) -> bool) -> Self {
+        let mut stack = Vec::with_capacity(node.height + 1);
+        loop {
+            let index = node.pivots().partition_point(&mut before);
+            stack.push((node, index));
+            if node.is_leaf() {
+                break;
+            }
+            node = node.child(index);
+        }
+        let mut front = Front { stack };
+        front.pop_finished();
+        front
+    }
+
+    fn pop_finished(&mut self) {
+        while let Some((node, index)) = self.stack.last() {
+            if *index < node.pivots().len() {
+                break;
+            }
+            self.stack.pop();
+        }
+    }
+
+    fn peek(&self) -> Option<&'a T> {
+        let (node, index) = self.stack.last()?;
+        Some(&node.pivots()[*index])
+    }
+
+    fn advance(&mut self) -> Option<&'a T> {
+        let (node, index) = self.stack.last_mut()?;
+        let node = *node;
+        let value = &node.pivots()[*index];
+        *index += 1;
+        if node.is_leaf() {
+            self.pop_finished();
+        } else {
+            // every node below the root holds pivots, so the leftmost leaf is never finished.
+            let mut child = node.child(*index);
+            while !child.is_leaf() {
+                self.stack.push((child, 0));
+                child = child.child(0);
+            }
+            self.stack.push((child, 0));
+        }
+        Some(value)
+    }
+}
+
+/// The back end of an iteration: the path from the root to the next element to yield from
+/// the back, with the number of pivots not yet yielded in each node.
+///
+/// As with [`Front`], finished frames are popped eagerly.
+struct Back<'a, T, const M: usize> {
+    stack: Vec<(NodeRef<'a, T, M>, usize)>,
+}
+
+impl<'a, T, const M: usize> Back<'a, T, M> {
+    /// Seek to the last element of the subtree at `node` for which `within` returns true.
+    /// `within` must be true for a prefix of the elements.
+    fn seek(mut node: NodeRef<'a, T, M>, mut within: impl FnMut(&T) -> bool) -> Self {
+        let mut stack = Vec::with_capacity(node.height + 1);
+        loop {
+            let index = node.pivots().partition_point(&mut within);
+            stack.push((node, index));
+            if node.is_leaf() {
+                break;
+            }
+            node = node.child(index);
+        }
+        let mut back = Back { stack };
+        back.pop_finished();
+        back
+    }
+
+    fn pop_finished(&mut self) {
+        while let Some((_, 0)) = self.stack.last() {
+            self.stack.pop();
+        }
+    }
+
+    fn peek(&self) -> Option<&'a T> {
+        let (node, index) = self.stack.last()?;
+        Some(&node.pivots()[*index - 1])
+    }
+
+    fn advance(&mut self) -> Option<&'a T> {
+        let (node, index) = self.stack.last_mut()?;
+        let node = *node;
+        *index -= 1;
+        let value = &node.pivots()[*index];
+        if node.is_leaf() {
+            self.pop_finished();
+        } else {
+            let mut child = node.child(*index);
+            while !child.is_leaf() {
+                let len = child.pivots().len();
+                self.stack.push((child, len));
+                child = child.child(len);
+            }
+            self.stack.push((child, child.pivots().len()));
+        }
+        Some(value)
+    }
+}
+
+impl<T, const M: usize> Clone for Front<'_, T, M> {
+    fn clone(&self) -> Self {
+        Front {
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl<T, const M: usize> Clone for Back<'_, T, M> {
+    fn clone(&self) -> Self {
+        Back {
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+/// An iterator over the elements of an [`OkBTree`] in ascending order, from [`OkBTree::iter`].
+pub struct Iter<'a, T, const M: usize = 8> {
+    root: Option<NodeRef<'a, T, M>>,
+    /// Seeked to the first element on the first call to `next`.
+    front: Option<Front<'a, T, M>>,
+    /// Seeked to the last element on the first call to `next_back`.
+    back: Option<Back<'a, T, M>>,
+    /// The number of elements between the two ends, which stops them from crossing.
+    remaining: usize,
+}
+
+impl<T, const M: usize> OkBTree<T, M> {
+    fn root(&self) -> Option<NodeRef<'_, T, M>> {
+        self.0.as_ref().map(|inner| NodeRef {
+            height: inner.depth.get() - 1,
+            node: &inner.node,
+        })
+    }
+
+    /// Iterate over the elements in ascending order.
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter {
+            root: self.root(),
+            front: None,
+            back: None,
+            remaining: self.len(),
+        }
+    }
+}
+
+impl<'a, T, const M: usize> Iterator for Iter<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let root = self.root?;
+        let front = self
+            .front
+            .get_or_insert_with(|| Front::seek(root, |_| false));
+        let value = front.advance()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, const M: usize> DoubleEndedIterator for Iter<'a, T, M> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let root = self.root?;
+        let back = self.back.get_or_insert_with(|| Back::seek(root, |_| true));
+        let value = back.advance()?;
+        self.remaining -= 1;
+        Some(value)
+    }
+}
+
+impl<T, const M: usize> ExactSizeIterator for Iter<'_, T, M> {}
+
+impl<T, const M: usize> FusedIterator for Iter<'_, T, M> {}
+
+impl<T, const M: usize> Clone for Iter<'_, T, M> {
+    fn clone(&self) -> Self {
+        Iter {
+            root: self.root,
+            front: self.front.clone(),
+            back: self.back.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<T, const M: usize> OkBTree<T, M> {
+    /// Iterate over every run of `K` consecutive elements in ascending order, like
+    /// [`slice::windows`], without collecting the elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `K` is 0.
+    pub fn iter_windows<const K: usize>(&self) -> Windows<'_, T, M, K> {
+        assert!(K != 0, "window size must be non-zero");
+        Windows {
+            iter: self.iter(),
+            window: None,
+        }
+    }
+}
+
+/// An iterator over the overlapping windows of `K` consecutive elements of an [`OkBTree`],
+/// from [`OkBTree::iter_windows`].
+pub struct Windows<'a, T, const M: usize, const K: usize> {
+    iter: Iter<'a, T, M>,
+    /// The last window returned, once the first has been filled.
+    window: Option<[&'a T; K]>,
+}
+
+impl<'a, T, const M: usize, const K: usize> Iterator for Windows<'a, T, M, K> {
+    type Item = [&'a T; K];
+
+    fn next(&mut self) -> Option<[&'a T; K]> {
+        match &mut self.window {
+            Some(window) => {
+                let value = self.iter.next()?;
+                window.rotate_left(1);
+                window[K - 1] = value;
+            }
+            None => {
+                let mut window = [None; K];
+                for slot in &mut window {
+                    *slot = Some(self.iter.next()?);
+                }
+                self.window = Some(window.map(Option::unwrap));
+            }
+        }
+        self.window
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self.window {
+            Some(_) => self.iter.len(),
+            None => self.iter.len().saturating_sub(K - 1),
+        };
+        (len, Some(len))
+    }
+}
+
+impl<T, const M: usize, const K: usize> ExactSizeIterator for Windows<'_, T, M, K> {}
+
+impl<T, const M: usize, const K: usize> FusedIterator for Windows<'_, T, M, K> {}
+
+impl<T, const M: usize, const K: usize> Clone for Windows<'_, T, M, K> {
+    fn clone(&self) -> Self {
+        Windows {
+            iter: self.iter.clone(),
+            window: self.window,
+        }
+    }
+}
+
+/// An iterator over the elements of an [`OkBTree`] within a range, in ascending order, from
+/// [`OkBTree::range`].
+pub struct Range<'a, T, const M: usize = 8> {
+    front: Front<'a, T, M>,
+    back: Back<'a, T, M>,
+    /// Set once the two ends have met.
+    done: bool,
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Iterate over the elements within `range` in ascending order.
+    ///
+    /// Both ends are found by binary search, in `O(log n)` time. Unlike
+    /// [`BTreeSet::range`](std::collections::BTreeSet::range), a range whose start is after
+    /// its end is empty rather than a panic.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, T, M>
+    where
+        Q: Comparable<T> + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let Some(root) = self.root() else {
+            return Range {
+                front: Front { stack: Vec::new() },
+                back: Back { stack: Vec::new() },
+                done: true,
+            };
+        };
+
+        let front = match range.start_bound() {
+            Bound::Included(lo) => Front::seek(root, |p| lo.compare(p).is_gt()),
+            Bound::Excluded(lo) => Front::seek(root, |p| lo.compare(p).is_ge()),
+            Bound::Unbounded => Front::seek(root, |_| false),
+        };
+        let back = match range.end_bound() {
+            Bound::Included(hi) => Back::seek(root, |p| hi.compare(p).is_ge()),
+            Bound::Excluded(hi) => Back::seek(root, |p| hi.compare(p).is_gt()),
+            Bound::Unbounded => Back::seek(root, |_| true),
+        };
+
+        // with duplicates, equal first and last elements are the first and last copies of
+        // the same value, so the ends can only have crossed if the values are out of order.
+        let done = match (front.peek(), back.peek()) {
+            (Some(first), Some(last)) => first > last,
+            _ => true,
+        };
+        Range { front, back, done }
+    }
+}
+
+impl<'a, T, const M: usize> Range<'a, T, M> {
+    /// The first and last elements left in the range, by position in the tree.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn ends(&self) -> Option<(&'a T, &'a T)> {
+        if self.done {
+            return None;
+        }
+        Some((self.front.peek()?, self.back.peek()?))
+    }
+}
+
+impl<'a, T, const M: usize> Iterator for Range<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+        let value = self.front.advance()?;
+        self.done = self.back.peek().map_or(true, |last| ptr::eq(value, last));
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::from(!self.done), None)
+    }
+}
+
+impl<'a, T, const M: usize> DoubleEndedIterator for Range<'a, T, M> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+        let value = self.back.advance()?;
+        self.done = self
+            .front
+            .peek()
+            .map_or(true, |first| ptr::eq(value, first));
+        Some(value)
+    }
+}
+
+impl<T, const M: usize> FusedIterator for Range<'_, T, M> {}
+
+impl<T, const M: usize> Clone for Range<'_, T, M> {
+    fn clone(&self) -> Self {
+        Range {
+            front: self.front.clone(),
+            back: self.back.clone(),
+            done: self.done,
+        }
+    }
+}
+
+/// An iterator over the elements of an [`OkBTree`] from a starting point to the end, in
+/// ascending order, from [`OkBTree::iter_from`].
+pub struct IterFrom<'a, T, const M: usize = 8> {
+    front: Front<'a, T, M>,
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Iterate over every element equal to `q`, in the order they are stored.
+    ///
+    /// With [`DuplicatePolicy::AllowDuplicates`](crate::DuplicatePolicy::AllowDuplicates)
+    /// this can yield any number of elements, otherwise at most one.
+    pub fn get_all<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Range<'_, T, M> {
+        self.range::<Q, _>((Bound::Included(q), Bound::Included(q)))
+    }
+
+    /// Iterate in ascending order from the first element that is not less than `q` to the
+    /// end of the tree.
+    ///
+    /// This only seeks one end, so it is cheaper than [`OkBTree::range`] with an unbounded
+    /// end, but cannot be iterated from the back.
+    pub fn iter_from<Q>(&self, q: &Q) -> IterFrom<'_, T, M>
+    where
+        Q: Comparable<T> + ?Sized,
+    {
+        let front = match self.root() {
+            Some(root) => Front::seek(root, |p| q.compare(p).is_gt()),
+            None => Front { stack: Vec::new() },
+        };
+        IterFrom { front }
+    }
+}
+
+impl<'a, T, const M: usize> Iterator for IterFrom<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.front.advance()
+    }
+}
+
+impl<T, const M: usize> FusedIterator for IterFrom<'_, T, M> {}
+
+impl<T, const M: usize> Clone for IterFrom<'_, T, M> {
+    fn clone(&self) -> Self {
+        IterFrom {
+            front: self.front.clone(),
+        }
+    }
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Iterate over the runs of consecutive elements that `f` maps to the same key, as the
+    /// key and a [`Range`] over the run.
+    ///
+    /// Runs are only merged while they are adjacent, so `f` should be monotonic, like the
+    /// day of a timestamp, for each key to appear once.
+    pub fn group_ranges_by<K, F>(&self, f: F) -> GroupRangesBy<'_, T, M, K, F>
+    where
+        K: PartialEq,
+        F: FnMut(&T) -> K,
+    {
+        GroupRangesBy {
+            tree: self,
+            iter: self.iter(),
+            next: None,
+            f,
+        }
+    }
+}
+
+/// An iterator over the runs of elements with equal keys, from [`OkBTree::group_ranges_by`].
+pub struct GroupRangesBy<'a, T, const M: usize, K, F> {
+    tree: &'a OkBTree<T, M>,
+    iter: Iter<'a, T, M>,
+    /// The first element of the next run, already taken from `iter`, with its key.
+    next: Option<(&'a T, K)>,
+    f: F,
+}
+
+impl<'a, T: Ord, const M: usize, K, F> Iterator for GroupRangesBy<'a, T, M, K, F>
+where
+    K: PartialEq,
+    F: FnMut(&T) -> K,
+{
+    type Item = (K, Range<'a, T, M>);
+
+    fn next(&mut self) -> Option<(K, Range<'a, T, M>)> {
+        let (first, key) = match self.next.take() {
+            Some(next) => next,
+            None => {
+                let first = self.iter.next()?;
+                (first, (self.f)(first))
+            }
+        };
+
+        let mut last = first;
+        for value in &mut self.iter {
+            let next = (self.f)(value);
+            if next != key {
+                self.next = Some((value, next));
+                break;
+            }
+            last = value;
+        }
+        // equal elements share a key, so no copy of `first` or `last` lies outside the run.
+        Some((key, self.tree.range::<T, _>(first..=last)))
+    }
+}
+
+impl<T: Ord, const M: usize, K, F> FusedIterator for GroupRangesBy<'_, T, M, K, F>
+where
+    K: PartialEq,
+    F: FnMut(&T) -> K,
+{
+}
+
+impl<T, const M: usize> OkBTree<T, M> {
+    /// Iterate over the nodes of the tree level by level, starting from the root, and from
+    /// left to right within each level.
+    pub fn iter_nodes_breadth_first(&self) -> NodesBreadthFirst<'_, T, M> {
+        NodesBreadthFirst {
+            queue: self.root().into_iter().collect(),
+        }
+    }
+
+    /// Read every node, level by level from the root, so that a tree that has just been
+    /// built or loaded is in the cache before serving latency-sensitive queries.
+    ///
+    /// Each node is read a byte per cache line, as raw memory rather than as elements.
+    /// This takes `&mut self` so that nothing, not even an atomic inside an element, can be
+    /// written to while it is read.
+    pub fn warm(&mut self) {
+        const CACHE_LINE: usize = 64;
+
+        let bytes = mem::size_of::<NodeArray<T, M>>();
+        // the last byte too, in case the node ends partway into a line.
+        let offsets = (0..bytes).step_by(CACHE_LINE).chain(bytes.checked_sub(1));
+        for node in self.iter_nodes_breadth_first() {
+            let start = (node.node as *const NodeArray<T, M>).cast::<MaybeUninit<u8>>();
+            for offset in offsets.clone() {
+                // SAFETY: offset is within the node, which is borrowed here and can't be
+                // written through any other reference. Uninit bytes are read as MaybeUninit.
+                black_box(unsafe { ptr::read_volatile(start.add(offset)) });
+            }
+        }
+    }
+}
+
+/// An iterator over the nodes of an [`OkBTree`] in breadth-first order, from
+/// [`OkBTree::iter_nodes_breadth_first`].
+pub struct NodesBreadthFirst<'a, T, const M: usize = 8> {
+    queue: VecDeque<NodeRef<'a, T, M>>,
+}
+
+impl<'a, T, const M: usize> Iterator for NodesBreadthFirst<'a, T, M> {
+    type Item = NodeRef<'a, T, M>;
+
+    fn next(&mut self) -> Option<NodeRef<'a, T, M>> {
+        let node = self.queue.pop_front()?;
+        self.queue
+            .extend((0..node.child_count()).map(|index| node.child(index)));
+        Some(node)
+    }
+}
+
+impl<T, const M: usize> FusedIterator for NodesBreadthFirst<'_, T, M> {}
+
+impl<'a, T, const M: usize> IntoIterator for &'a OkBTree<T, M> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, M>;
+
+    fn into_iter(self) -> Iter<'a, T, M> {
+        self.iter()
+    }
+}
+
+/// An owning iterator over the elements of an [`OkBTree`] in ascending order, from its
+/// `IntoIterator` impl.
+///
+/// Nodes are taken apart as the iterator reaches them, without any rebalancing, and each
+/// node is freed as soon as its elements and children have been moved out.
+pub struct IntoIter<T, const M: usize = 8> {
+    /// The rest of the tree in order: single elements, and whole subtrees that are split up
+    /// when the iterator reaches them.
+    parts: VecDeque<OwnedPart<T, M>>,
+    remaining: usize,
+}
+
+enum OwnedPart<T, const M: usize> {
+    Element(T),
+    Node(OwnedNode<T, M>),
+}
+
+impl<T, const M: usize> IntoIterator for OkBTree<T, M> {
+    type Item = T;
+    type IntoIter = IntoIter<T, M>;
+
+    fn into_iter(mut self) -> IntoIter<T, M> {
+        let remaining = self.len();
+        let parts = self
+            .0
+            .take()
+            .map(|inner| {
+                OwnedPart::Node(OwnedNode {
+                    height: inner.depth.get() - 1,
+                    node: inner.node,
+                })
+            })
+            .into_iter()
+            .collect();
+        IntoIter { parts, remaining }
+    }
+}
+
+impl<T, const M: usize> Iterator for IntoIter<T, M> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            match self.parts.pop_front()? {
+                OwnedPart::Element(value) => {
+                    self.remaining -= 1;
+                    return Some(value);
+                }
+                OwnedPart::Node(node) => {
+                    let height = node.height;
+                    let (head, pivots, children) = node.split();
+                    let Some(head) = head else {
+                        pivots
+                            .rev()
+                            .for_each(|value| self.parts.push_front(OwnedPart::Element(value)));
+                        continue;
+                    };
+                    for (pivot, child) in pivots.rev().zip(children.rev()) {
+                        self.parts.push_front(OwnedPart::Node(OwnedNode {
+                            height: height - 1,
+                            node: child,
+                        }));
+                        self.parts.push_front(OwnedPart::Element(pivot));
+                    }
+                    self.parts.push_front(OwnedPart::Node(head));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, const M: usize> DoubleEndedIterator for IntoIter<T, M> {
+    fn next_back(&mut self) -> Option<T> {
+        loop {
+            match self.parts.pop_back()? {
+                OwnedPart::Element(value) => {
+                    self.remaining -= 1;
+                    return Some(value);
+                }
+                OwnedPart::Node(node) => {
+                    let height = node.height;
+                    let (head, pivots, children) = node.split();
+                    let Some(head) = head else {
+                        pivots.for_each(|value| self.parts.push_back(OwnedPart::Element(value)));
+                        continue;
+                    };
+                    self.parts.push_back(OwnedPart::Node(head));
+                    for (pivot, child) in pivots.zip(children) {
+                        self.parts.push_back(OwnedPart::Element(pivot));
+                        self.parts.push_back(OwnedPart::Node(OwnedNode {
+                            height: height - 1,
+                            node: child,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T, const M: usize> ExactSizeIterator for IntoIter<T, M> {}
+
+impl<T, const M: usize> FusedIterator for IntoIter<T, M> {}
+
+#[cfg(test)]
+mod test {
+    use super::Range;
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn iter() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            assert_eq!(btree.iter().next(), None);
+
+            for i in (0..1000).rev() {
+                btree.insert(i);
+            }
+            for i in (0..1000).step_by(3) {
+                btree.remove(&i);
+            }
+            let expected: Vec<i32> = (0..1000).filter(|i| i % 3 != 0).collect();
+
+            let mut iter = btree.iter();
+            assert_eq!(iter.len(), expected.len());
+            iter.next();
+            assert_eq!(iter.len(), expected.len() - 1);
+
+            assert_eq!(btree.iter().copied().collect::<Vec<_>>(), expected);
+            let mut borrowed = vec![];
+            for value in &btree {
+                borrowed.push(*value);
+            }
+            assert_eq!(borrowed, expected);
+        }
+
+        check::<2>();
+        check::<4>();
+        check::<8>();
+    }
+
+    #[test]
+    fn double_ended() {
+        fn check<const M: usize>() {
+            let btree: OkBTree<i32, M> = OkBTree::from_sorted_unchecked(0..500);
+            let expected: Vec<i32> = (0..500).rev().collect();
+            assert_eq!(btree.iter().rev().copied().collect::<Vec<_>>(), expected);
+
+            // alternate ends until they meet, at every possible meeting point
+            for split in 0..=500 {
+                let mut iter = btree.iter();
+                let mut into_iter = OkBTree::<i32, M>::from_sorted_unchecked(0..500).into_iter();
+                let mut seen = vec![];
+                for i in 0..500 {
+                    let (a, b) = if i < split {
+                        (iter.next(), into_iter.next())
+                    } else {
+                        (iter.next_back(), into_iter.next_back())
+                    };
+                    assert_eq!(a.copied(), b);
+                    seen.push(a.copied().unwrap());
+                }
+                assert_eq!((iter.next(), iter.next_back()), (None, None));
+                assert_eq!((into_iter.next(), into_iter.next_back()), (None, None));
+                seen.sort();
+                assert_eq!(seen, (0..500).collect::<Vec<_>>());
+            }
+
+            let mut iter = btree.iter();
+            let mut into_iter = OkBTree::<i32, M>::from_sorted_unchecked(0..500).into_iter();
+            for i in 0..250 {
+                assert_eq!(iter.next(), Some(&i));
+                assert_eq!(iter.next_back(), Some(&(499 - i)));
+                assert_eq!(into_iter.next_back(), Some(499 - i));
+                assert_eq!(into_iter.next(), Some(i));
+            }
+            assert_eq!(iter.len(), 0);
+            assert_eq!((iter.next(), into_iter.next_back()), (None, None));
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn range() {
+        use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+        fn check<const M: usize>() {
+            // the odd numbers, so bounds fall both on and between elements
+            let btree: OkBTree<i32, M> =
+                OkBTree::from_sorted_unchecked((0..200).map(|i| i * 2 + 1));
+            let model: std::collections::BTreeSet<i32> = btree.iter().copied().collect();
+
+            let bounds = |i: i32| [Included(i), Excluded(i), Unbounded];
+            for lo in (-2..=402).step_by(7) {
+                for hi in (-2..=402).step_by(11) {
+                    for lo in bounds(lo) {
+                        for hi in bounds(hi) {
+                            let r: (Bound<i32>, Bound<i32>) = (lo, hi);
+                            let expected: Vec<i32> = match (lo, hi) {
+                                (Included(a) | Excluded(a), Included(b) | Excluded(b))
+                                    if a > b || (a == b && r != (Included(a), Included(b))) =>
+                                {
+                                    vec![]
+                                }
+                                _ => model.range(r).copied().collect(),
+                            };
+                            assert_eq!(btree.range(r).copied().collect::<Vec<_>>(), expected);
+
+                            let mut rev = expected.clone();
+                            rev.reverse();
+                            assert_eq!(btree.range(r).rev().copied().collect::<Vec<_>>(), rev);
+
+                            // meet in the middle
+                            let mut iter = btree.range(r);
+                            let mut seen = vec![];
+                            while let Some(a) = iter.next() {
+                                seen.push(*a);
+                                match iter.next_back() {
+                                    Some(b) => seen.push(*b),
+                                    None => break,
+                                }
+                            }
+                            assert_eq!((iter.next(), iter.next_back()), (None, None));
+                            seen.sort();
+                            assert_eq!(seen, expected);
+                        }
+                    }
+                }
+            }
+        }
+
+        check::<2>();
+        check::<8>();
+
+        assert_eq!(OkBTree::<i32>::new().range::<i32, _>(..).next(), None);
+
+        let mut multiset = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..60 {
+            multiset.insert(i % 3);
+        }
+        assert_eq!(multiset.range(1..=1).count(), 20);
+        assert_eq!(multiset.range(1..2).rev().count(), 20);
+        assert_eq!(multiset.range(1..1).count(), 0);
+        assert_eq!(multiset.range((Excluded(0), Unbounded)).count(), 40);
+    }
+
+    #[test]
+    fn iter_from() {
+        fn check<const M: usize>() {
+            let btree: OkBTree<i32, M> =
+                OkBTree::from_sorted_unchecked((0..200).map(|i| i * 2 + 1));
+            for q in -2..=402 {
+                let expected: Vec<i32> = btree.range(q..).copied().collect();
+                assert_eq!(btree.iter_from(&q).copied().collect::<Vec<_>>(), expected);
+            }
+            assert_eq!(OkBTree::<i32, M>::default().iter_from(&0).next(), None);
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn nodes_breadth_first() {
+        let mut btree: OkBTree<i32, 2> = OkBTree::from_sorted_unchecked(0..100);
+        btree.warm();
+        // padding and uninit slots are read too, and elements needn't be Copy
+        OkBTree::<(u8, u32), 2>::from_sorted_unchecked((0..100).map(|i| (i, 0))).warm();
+        OkBTree::<String, 2>::from_sorted_unchecked((100..200).map(|i| i.to_string())).warm();
+
+        let mut visited = 0;
+        btree.visit_nodes(|_| visited += 1);
+        let nodes: Vec<_> = btree.iter_nodes_breadth_first().collect();
+        assert_eq!(nodes.len(), visited);
+        assert!(nodes.windows(2).all(|w| w[0].height() >= w[1].height()));
+
+        // each level is in order from left to right
+        for pair in nodes.windows(2) {
+            if pair[0].height() == pair[1].height() {
+                assert!(pair[0].pivots().last() < pair[1].pivots().first());
+            }
+        }
+        assert_eq!(OkBTree::<i32>::new().iter_nodes_breadth_first().count(), 0);
+    }
+
+    #[test]
+    fn iter_windows() {
+        fn check<const K: usize>() {
+            for len in 0..20 {
+                let btree: OkBTree<i32, 2> = (0..len).collect();
+                let values: Vec<i32> = (0..len).collect();
+                let windows = btree.iter_windows::<K>();
+                assert_eq!(windows.len(), values.windows(K).len());
+                assert!(windows
+                    .map(|window| window.map(|v| *v))
+                    .eq(values.windows(K).map(|w| <[i32; K]>::try_from(w).unwrap())));
+            }
+        }
+
+        check::<1>();
+        check::<2>();
+        check::<3>();
+        check::<5>();
+    }
+
+    #[test]
+    fn group_ranges_by() {
+        const DAY: u64 = 86_400;
+
+        fn collect<'a, K>(
+            groups: impl Iterator<Item = (K, Range<'a, u64, 2>)>,
+        ) -> Vec<(K, Vec<u64>)> {
+            groups
+                .map(|(key, range)| (key, range.copied().collect()))
+                .collect()
+        }
+
+        let mut btree = OkBTree::<u64, 2>::default();
+        assert!(btree.group_ranges_by(|t| t / DAY).next().is_none());
+
+        for t in [10, 20, 30, DAY + 5, 3 * DAY, 3 * DAY + 1] {
+            btree.insert(t);
+        }
+        assert_eq!(
+            collect(btree.group_ranges_by(|t| t / DAY)),
+            [
+                (0, vec![10, 20, 30]),
+                (1, vec![DAY + 5]),
+                (3, vec![3 * DAY, 3 * DAY + 1]),
+            ]
+        );
+
+        // copies at either end of a run stay within it
+        let mut multiset = OkBTree::<u64, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for t in (0..40).chain([9, 9, 10, 10, 19, 20, 20]) {
+            multiset.insert(t);
+        }
+        let groups = collect(multiset.group_ranges_by(|t| t / 10));
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[0].1, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 9, 9]);
+        assert_eq!(
+            groups[1].1,
+            [10, 10, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 19]
+        );
+        assert_eq!(groups[2].1.len(), 12);
+        let total: usize = groups.iter().map(|(_, range)| range.len()).sum();
+        assert_eq!(total, multiset.len());
+    }
+
+    #[test]
+    fn into_iter() {
+        use std::rc::Rc;
+
+        fn check<const M: usize>() {
+            let btree: OkBTree<i32, M> = OkBTree::from_sorted_unchecked(0..1000);
+            let iter = btree.into_iter();
+            assert_eq!(iter.len(), 1000);
+            assert_eq!(iter.collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+
+            // the rest of the elements are dropped with the iterator
+            let counter = Rc::new(());
+            let mut btree = OkBTree::<_, M>::default();
+            for i in 0..1000 {
+                btree.insert((i, counter.clone()));
+            }
+            let mut iter = btree.into_iter();
+            for i in 0..300 {
+                assert_eq!(iter.next().map(|v| v.0), Some(i));
+            }
+            assert_eq!(iter.len(), 700);
+            assert_eq!(Rc::strong_count(&counter), 701);
+            drop(iter);
+            assert_eq!(Rc::strong_count(&counter), 1);
+
+            assert_eq!(OkBTree::<i32, M>::default().into_iter().next(), None);
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+}
@@ -1,774 +1,889 @@
 #![warn(unsafe_op_in_unsafe_fn)]
 
 use std::{
-    hint::unreachable_unchecked,
-    mem::{self, MaybeUninit},
+    iter::Peekable,
+    mem,
     num::NonZeroUsize,
+    ops::{Bound, RangeBounds},
+    panic::{RefUnwindSafe, UnwindSafe},
     ptr::{addr_of, addr_of_mut},
 };
 
 use arrayvec::DetachedArrayVec;
+pub use batch::InsertBatch;
+pub use bounds::{after, before, Discrete};
+pub use builder::OkBTreeBuilder;
+#[cfg(feature = "metrics")]
+pub use clock::StdClock;
+pub use clock::{Clock, ManualClock};
+pub use display::DisplayTree;
 use equivalent::Comparable;
+pub use frozen::FrozenBTree;
+pub use handle::{ReadGuard, ReadHandle, WriteHandle};
+pub use histogram::{Bucket, Gap};
+pub use iter::{GroupRangesBy, IntoIter, Iter, IterFrom, NodesBreadthFirst, Range, Windows};
+pub use node::NodeRef;
+use node::{
+    At, BinarySearch, Children, Comp, First, FirstAt, InsertMode, InsertResult, Last, NodeArray,
+    NodeArrayFmt, OwnedNode, RemoveResult, Slot,
+};
+pub use node_stream::{FromNodeStreamError, NodeDesc};
+#[cfg(feature = "rayon")]
+pub use par::ParRange;
+pub use partitioned::{PartitionedBTree, PartitionedRange};
+pub use rng::SplitMix64;
+pub use transaction::Transaction;
+pub use validate::{Corruption, CorruptionError};
+pub use watch::{Event, WatchId, Watched};
+pub use zip::{EitherOrBoth, ZipByKey};
 
 mod arrayvec;
+mod batch;
+mod bounds;
+mod builder;
+mod clock;
+mod display;
+mod frozen;
+mod handle;
+mod histogram;
+mod iter;
+mod macros;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "unstable")]
+pub mod node;
+#[cfg(not(feature = "unstable"))]
+mod node;
+mod node_stream;
+#[cfg(feature = "rayon")]
+mod par;
+mod partitioned;
+pub mod rng;
+mod transaction;
+mod validate;
+mod watch;
+#[cfg(feature = "workload")]
+pub mod workload;
+mod zip;
+
+/// An ordered set backed by a B-tree.
+///
+/// `M` is the maximum number of elements stored in each node. It must be even and
+/// greater than one. Wider nodes mean fewer levels to descend through, at the cost of
+/// more elements to shift around on insertion and removal. Every level of the tree uses
+/// the same fanout: leaves and internal nodes are the same node type, and splits, merges
+/// and rotations move elements between nodes of equal capacity.
+///
+/// What happens when an element equal to one already in the tree is inserted is decided
+/// by the tree's [`DuplicatePolicy`]. By default, the new element replaces the old one.
+///
+/// The tree owns its elements like a `Vec` would: it is `Send`, `Sync`, `UnwindSafe` and
+/// `RefUnwindSafe` exactly when `T` is, and it is covariant in `T`.
+pub struct OkBTree<T, const M: usize = 8>(Option<BTreeInner<T, M>>, DuplicatePolicy);
+
+// The node internals are built from `MaybeUninit` and raw pointer casts, so check that the
+// auto traits and variance above still follow `T`. The reverse directions are in the tests.
+#[allow(dead_code)]
+const _: () = {
+    fn auto_traits<T: Send + Sync + UnwindSafe + RefUnwindSafe, const M: usize>() {
+        fn check<T: Send + Sync + UnwindSafe + RefUnwindSafe>() {}
+        check::<OkBTree<T, M>>();
+        check::<NodeRef<'_, T, M>>();
+    }
 
-const M: usize = 8;
-// const M: usize = 2;
-
-impl<T, const M: usize> Children<T, M> {
-    const fn new() -> Self {
-        Self {
-            head: MaybeUninit::uninit(),
-            tail: DetachedArrayVec::new(),
-        }
+    fn covariant<'a, const M: usize>(tree: OkBTree<&'static str, M>) -> OkBTree<&'a str, M> {
+        tree
     }
+};
+
+/// How [`OkBTree::insert`] handles an element equal to one already in the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Replace the existing element, returning it.
+    #[default]
+    Overwrite,
+    /// Keep the existing element and drop the new one.
+    KeepExisting,
+    /// Keep the existing element and return the new one.
+    Reject,
+    /// Insert the new element after all equal elements, making the tree a multiset.
+    ///
+    /// Lookups and removals then find any one of the equal elements.
+    AllowDuplicates,
 }
 
-struct NodeArray<T, const M: usize> {
+pub struct BTreeInner<T, const M: usize> {
+    depth: NonZeroUsize,
+    /// The number of elements in the tree.
     len: usize,
-    pivots: DetachedArrayVec<T, M>,
-    // empty if height = 0
-    children: Children<T, M>,
+    node: Box<NodeArray<T, M>>,
 }
 
-impl<T, const M: usize> NodeArray<T, M> {
-    /// # Safety
-    /// height must be correct.
-    unsafe fn drop_inner(&mut self, height: usize) {
-        if std::mem::needs_drop::<T>() {
-            // SAFETY: len pivots are init
-            unsafe { self.pivots.clear(self.len) };
-        }
-        if height > 0 {
-            debug_assert!(self.len > 0);
-            // SAFETY: internal nodes must always have children
-            unsafe { self.children.head.assume_init_read().drop_inner(height - 1) };
-
-            let tail = self.children.tail.take();
-
-            // SAFETY: len children are init in the tail.
-            for mut c in unsafe { tail.into_iter(self.len) } {
-                // SAFETY: height is correct and doesn't underflow.
-                unsafe { c.drop_inner(height - 1) };
+impl<T: std::fmt::Debug, const M: usize> std::fmt::Debug for OkBTree<T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(node) = &self.0 {
+            NodeArrayFmt {
+                height: node.depth.get() - 1,
+                array: &node.node,
             }
+            .fmt(f)
+        } else {
+            f.debug_list().finish()
         }
-        self.len = 0;
     }
 }
 
-struct Children<T, const M: usize> {
-    head: MaybeUninit<Box<NodeArray<T, M>>>,
-    tail: DetachedArrayVec<Box<NodeArray<T, M>>, M>,
+impl<T, const M: usize> Drop for OkBTree<T, M> {
+    fn drop(&mut self) {
+        self.clear();
+    }
 }
 
-impl<T, const M: usize> Children<T, M> {
-    // fn get(&self, len: usize, index: usize) -> &NodeArray<T, M> {
-    //     match index.checked_sub(1) {
-    //         // SAFETY: head is always init when height > 0
-    //         None => unsafe { self.head.assume_init_ref() },
-    //         // SAFETY: tail len are init
-    //         Some(index) => unsafe { &self.tail.as_slice(len)[index] },
-    //     }
-    // }
-    fn get_mut(&mut self, len: usize, index: usize) -> &mut NodeArray<T, M> {
-        match index.checked_sub(1) {
-            // SAFETY: head is always init when height > 0
-            None => unsafe { self.head.assume_init_mut() },
-            // SAFETY: tail len are init
-            Some(index) => unsafe { self.tail.as_mut_slice(len).get_unchecked_mut(index) },
-        }
-    }
-    fn get_ptr_mut(this: *mut Self, index: usize) -> *mut NodeArray<T, M> {
-        let boxed_node = match index.checked_sub(1) {
-            // SAFETY: head is always init when height > 0
-            None => unsafe { addr_of_mut!((*this).head).cast() },
-            Some(index) => unsafe {
-                DetachedArrayVec::get_ptr_mut(addr_of_mut!((*this).tail), index)
-            },
-        };
-        unsafe { addr_of_mut!(**boxed_node) }
-    }
+/// Returned by [`OkBTree::from_sorted_iter`] and [`FrozenBTree::from_sorted`] when the values
+/// were not strictly increasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotSorted {
+    /// The position of the first value that was not greater than the one before it.
+    pub index: usize,
+}
 
-    unsafe fn push_front(&mut self, len: usize, t: Box<NodeArray<T, M>>) {
-        unsafe {
-            let head = mem::replace(self.head.assume_init_mut(), t);
-            self.tail.insert(len, 0, head);
-        }
-    }
-    unsafe fn pop_front(&mut self, len: usize) -> Box<NodeArray<T, M>> {
-        unsafe { mem::replace(self.head.assume_init_mut(), self.tail.remove(len, 0)) }
+impl std::fmt::Display for NotSorted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "value at index {} is out of order", self.index)
     }
 }
 
-impl<T: Ord, const M: usize> NodeArray<T, M> {
-    const __M_IS_GREATER_THAN_ONE: bool = {
-        assert!(M > 1, "The fanout factor, M, must be greater than one");
-        true
-    };
-    const __M_IS_EVEN: bool = {
-        assert!(M % 2 == 0, "The fanout factor, M, must be even");
-        true
-    };
-
-    #[cold]
-    fn insert_split(
-        &mut self,
-        index: usize,
-        value: T,
-        child: Option<Box<NodeArray<T, M>>>,
-    ) -> InsertResult<T, M> {
-        debug_assert_eq!(self.len, M);
-        debug_assert!(M >= 2);
-
-        let m2 = M / 2;
-        let m2m1 = m2 - 1;
-        let m2p1 = m2 + 1;
-        assert!(m2p1 <= M);
-        assert!(m2 > 0);
-
-        // we are creating a new node,
-        // the values being split off from rhs will be written here.
-        let mut new_node = NodeArray {
-            len: 0,
-            pivots: DetachedArrayVec::new(),
-            children: Children::<T, M>::new(),
-        };
+impl std::error::Error for NotSorted {}
 
-        let mid = match usize::cmp(&index, &m2) {
-            std::cmp::Ordering::Equal => unsafe {
-                // SAFETY: M pivots are init. m2 < M.
-                new_node.pivots = self.pivots.split_off(M, m2);
-
-                if let Some(child) = child {
-                    new_node.children.head.write(child);
-                    // SAFETY: M children are init. m2 < M.
-                    new_node.children.tail = self.children.tail.split_off(M, m2);
-                }
+/// Returned by [`OkBTree::modify_key`] when the modified element was equal to another
+/// element in the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyOrderViolation<T> {
+    /// The modified element, which is no longer in the tree.
+    pub value: T,
+}
 
-                value
-            },
-            std::cmp::Ordering::Less => unsafe {
-                new_node.pivots = self.pivots.split_off(M, m2);
-                let mid = self.pivots.pop(m2);
-                self.pivots.insert(m2m1, index, value);
-
-                if let Some(child) = child {
-                    new_node.children.tail = self.children.tail.split_off(M, m2);
-                    new_node.children.head.write(self.children.tail.pop(m2));
-                    self.children.tail.insert(m2m1, index, child);
-                }
+impl<T> std::fmt::Display for KeyOrderViolation<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("modified element is equal to another element")
+    }
+}
 
-                mid
-            },
-            std::cmp::Ordering::Greater => unsafe {
-                let index = index - m2p1;
-                new_node.pivots = self.pivots.split_off(M, m2p1);
-                let mid = self.pivots.pop(m2p1);
-                new_node.pivots.insert(m2m1, index, value);
-
-                if let Some(child) = child {
-                    new_node.children.tail = self.children.tail.split_off(M, m2p1);
-                    new_node.children.head.write(self.children.tail.pop(m2p1));
-                    new_node.children.tail.insert(m2m1, index, child);
-                }
+impl<T: std::fmt::Debug> std::error::Error for KeyOrderViolation<T> {}
 
-                mid
-            },
-        };
-        self.len = m2;
-        new_node.len = m2;
-        InsertResult::Propagate {
-            pivot: mid,
-            right: Box::new(new_node),
-        }
+impl<T> OkBTree<T> {
+    pub const fn new() -> Self {
+        OkBTree(None, DuplicatePolicy::Overwrite)
     }
+}
 
-    fn insert(&mut self, mut value: T, height: usize) -> InsertResult<T, M> {
-        assert!(Self::__M_IS_GREATER_THAN_ONE);
-        assert!(Self::__M_IS_EVEN);
+impl<T, const M: usize> OkBTree<T, M> {
+    /// Create an empty tree that handles equal elements according to `policy`.
+    pub const fn with_policy(policy: DuplicatePolicy) -> Self {
+        OkBTree(None, policy)
+    }
 
-        // SAFETY: `len` pivots are init
-        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.1
+    }
 
-        let index = match Comp::from_comp(&value).binary_search(pivots, height) {
-            Ok(index) => {
-                unsafe { *pivots.get_unchecked_mut(index) = value };
-                return InsertResult::Done;
-            }
-            Err(index) => index,
-        };
+    /// The number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |inner| inner.len)
+    }
 
-        let mut new_child = None;
-        // if this is an internal node, try and insert into the children
-        if height > 0 {
-            debug_assert!(self.len > 0, "non leaf nodes must have some children");
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-            let child = self.children.get_mut(self.len, index);
+    /// Remove and drop every element, freeing all nodes. The duplicate policy is kept.
+    ///
+    /// Nodes are freed depth-first, so the recursion is only as deep as the tree is tall.
+    pub fn clear(&mut self) {
+        if let Some(mut inner) = self.0.take() {
+            // SAFETY: height is set correctly.
+            unsafe { inner.node.drop_inner(inner.depth.get() - 1) }
+        }
+    }
 
-            match child.insert(value, height - 1) {
-                InsertResult::Done => return InsertResult::Done,
-                InsertResult::Propagate { pivot, right } => {
-                    value = pivot;
-                    new_child = Some(right);
-                }
+    /// Calls `f` on every node of the tree, depth-first with each node visited before its
+    /// children.
+    ///
+    /// Together with [`NodeRef::height`] and [`NodeRef::child_count`], the visiting order is
+    /// enough to reconstruct the exact shape of the tree, making this a basis for custom
+    /// serialization formats.
+    pub fn visit_nodes<'a>(&'a self, mut f: impl FnMut(NodeRef<'a, T, M>)) {
+        if let Some(inner) = &self.0 {
+            NodeRef {
+                height: inner.depth.get() - 1,
+                node: &inner.node,
             }
+            .visit(&mut f);
         }
+    }
+}
 
-        if self.len == M {
-            self.insert_split(index, value, new_child)
-        } else {
-            // SAFETY:
-            // * len children and pivots are currently init
-            // * len is less than cap.
-            unsafe {
-                self.pivots.insert(self.len, index, value);
-                if let Some(child) = new_child {
-                    self.children.tail.insert(self.len, index, child);
-                }
-                self.len += 1;
-            }
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    fn search<B: BinarySearch<T> + ?Sized>(&self, b: &B) -> Option<&T> {
+        let inner = self.0.as_ref()?;
+        unsafe {
+            let (index, child) = NodeArray::<T, M>::search_raw(
+                &*inner.node as *const NodeArray<T, M> as *mut _,
+                inner.depth.get() - 1,
+                b,
+            )?;
 
-            InsertResult::Done
+            let pivots = addr_of!((*child).pivots);
+            let value = DetachedArrayVec::get_ptr_mut(pivots.cast_mut(), index);
+            Some(&*value)
         }
     }
 
-    unsafe fn search_raw<B: BinarySearch<T>>(
-        this: *mut Self,
-        height: usize,
-        b: &B,
-    ) -> Option<(usize, *mut NodeArray<T, M>)> {
-        assert!(Self::__M_IS_GREATER_THAN_ONE);
-        assert!(Self::__M_IS_EVEN);
+    pub fn get<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Option<&T> {
+        self.search(Comp::from_comp(q))
+    }
 
-        // SAFETY: caller must assert that this is readable.
-        let len = unsafe { *addr_of!((*this).len) };
-        let pivots = unsafe { &*addr_of!((*this).pivots) };
+    pub fn contains<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> bool {
+        let Some(inner) = &self.0 else { return false };
+        // SAFETY: the tree is only read, and no element is dereferenced.
+        unsafe {
+            NodeArray::<T, M>::search_raw(
+                &*inner.node as *const NodeArray<T, M> as *mut _,
+                inner.depth.get() - 1,
+                Comp::from_comp(q),
+            )
+        }
+        .is_some()
+    }
+    /// Get the element equal to `q`, along with its rank: the number of smaller elements.
+    ///
+    /// Nodes don't track the size of their subtrees, so computing the rank counts every
+    /// element to the left of the search path, taking `O(rank)` time.
+    pub fn get_full<Q: Comparable<T> + ?Sized>(&self, q: &Q) -> Option<(usize, &T)> {
+        let inner = self.0.as_ref()?;
+        inner.node.get_full(inner.depth.get() - 1, q)
+    }
 
-        let index = {
-            // temporarily borrow pivots to perform the binary search.
-            // SAFETY: `len` pivots are init
-            let pivots = unsafe { pivots.as_slice(len) };
+    pub fn last(&self) -> Option<&T> {
+        self.search(&Last)
+    }
+    pub fn first(&self) -> Option<&T> {
+        self.search(&First)
+    }
 
-            match b.binary_search(pivots, height) {
-                Ok(index) => return Some((index, this)),
-                Err(index) => index,
-            }
+    /// The smallest element within `range`.
+    pub fn first_in_range<Q, R>(&self, range: R) -> Option<&T>
+    where
+        Q: Comparable<T> + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let inner = self.0.as_ref()?;
+        let value = inner
+            .node
+            .lower_bound(inner.depth.get() - 1, range.start_bound())?;
+        let below_end = match range.end_bound() {
+            Bound::Included(hi) => hi.compare(value).is_ge(),
+            Bound::Excluded(hi) => hi.compare(value).is_gt(),
+            Bound::Unbounded => true,
         };
+        below_end.then_some(value)
+    }
 
-        if height == 0 {
-            return None;
-        }
-
-        debug_assert!(len > 0, "non leaf nodes must have some children");
-        // SAFETY: caller must assert that this is readable.
-        // for height > 0, children are always init.
-        let children = unsafe { addr_of_mut!((*this).children) };
-        let child = Children::get_ptr_mut(children, index);
+    /// The largest element within `range`.
+    pub fn last_in_range<Q, R>(&self, range: R) -> Option<&T>
+    where
+        Q: Comparable<T> + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let inner = self.0.as_ref()?;
+        let value = inner
+            .node
+            .upper_bound(inner.depth.get() - 1, range.end_bound())?;
+        let above_start = match range.start_bound() {
+            Bound::Included(lo) => lo.compare(value).is_le(),
+            Bound::Excluded(lo) => lo.compare(value).is_lt(),
+            Bound::Unbounded => true,
+        };
+        above_start.then_some(value)
+    }
 
-        unsafe { Self::search_raw(child, height - 1, b) }
+    /// Get the element found by a custom search policy.
+    #[cfg(feature = "unstable")]
+    pub fn get_by<B: BinarySearch<T> + ?Sized>(&self, b: &B) -> Option<&T> {
+        self.search(b)
     }
 
-    // ok - no underflow
-    // err - underflow
-    fn remove<B: BinarySearch<T>>(&mut self, height: usize, b: &B) -> Option<RemoveResult<T>> {
-        assert!(Self::__M_IS_GREATER_THAN_ONE);
-        assert!(Self::__M_IS_EVEN);
+    /// Remove the element found by a custom search policy.
+    #[cfg(feature = "unstable")]
+    pub fn remove_by<B: BinarySearch<T> + ?Sized>(&mut self, b: &B) -> Option<T> {
+        self.remove_inner(b)
+    }
 
-        // SAFETY: `len` pivots are init
-        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
+    fn remove_inner<B: BinarySearch<T> + ?Sized>(&mut self, b: &B) -> Option<T> {
+        if let Some(inner) = &mut self.0 {
+            if inner.node.len == 0 {
+                return None;
+            };
+            let res = inner.node.remove(inner.depth.get() - 1, b)?;
+            inner.len -= 1;
+            match res {
+                RemoveResult::Done(val) => Some(val),
+                RemoveResult::Underflow(val) => {
+                    if inner.node.len == 0 && inner.depth.get() > 1 {
+                        inner.node = unsafe { inner.node.children.head.assume_init_read() };
+                        inner.depth = NonZeroUsize::new(inner.depth.get() - 1).unwrap();
+                    }
 
-        let binary_search = b.binary_search(pivots, height);
+                    Some(val)
+                }
+            }
+        } else {
+            None
+        }
+    }
 
-        // leaf node.
-        if height == 0 {
-            let index = b.binary_search(pivots, height).ok()?;
+    pub fn remove_last(&mut self) -> Option<T> {
+        self.remove_inner(&Last)
+    }
 
-            let value = unsafe { self.pivots.remove(self.len, index) };
-            self.len -= 1;
+    pub fn remove_first(&mut self) -> Option<T> {
+        self.remove_inner(&First)
+    }
 
-            if self.len < M / 2 {
-                return Some(RemoveResult::Underflow(value));
-            } else {
-                return Some(RemoveResult::Done(value));
-            }
-        }
+    pub fn remove<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<T> {
+        self.remove_inner(Comp::from_comp(q))
+    }
 
-        let (Ok(index) | Err(index)) = binary_search;
-        let child = self.children.get_mut(self.len, index);
-        let value = match binary_search {
-            Ok(_) => child
-                .remove(height - 1, &Last)?
-                .map(|v| std::mem::replace(unsafe { pivots.get_unchecked_mut(index) }, v)),
-            Err(_) => child.remove(height - 1, b)?,
-        };
-        let value = match value {
-            RemoveResult::Done(value) => return Some(RemoveResult::Done(value)),
-            RemoveResult::Underflow(value) => value,
-        };
+    /// Removes every element equal to `q`, yielding them in the order they were stored.
+    ///
+    /// Elements are removed as the iterator advances. If it is dropped early, the rest of
+    /// them are still removed. Each removal searches the tree twice: once to find the next
+    /// equal element, and once to remove it.
+    pub fn remove_all<'q, Q: Comparable<T> + ?Sized>(
+        &mut self,
+        q: &'q Q,
+    ) -> RemoveAll<'_, 'q, T, M, Q> {
+        RemoveAll { tree: self, q }
+    }
 
-        let index = match index.checked_sub(1) {
-            // SAFETY: head is always init when height > 0
-            None => unsafe {
-                let child = self.children.head.assume_init_mut();
-                let next_child = self
-                    .children
-                    .tail
-                    .as_mut_slice(self.len)
-                    .get_unchecked_mut(0);
-                let pivot = pivots.get_unchecked_mut(0);
-
-                if next_child.len > M / 2 {
-                    Self::rotate_left(height, child, pivot, next_child);
-                    return Some(RemoveResult::Done(value));
-                }
+    /// Removes and returns the element equal to `q`.
+    ///
+    /// This is the same as [`OkBTree::remove`], under the name used by
+    /// `std::collections::BTreeSet`.
+    pub fn take<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<T> {
+        self.remove(q)
+    }
 
-                // we can only merge
-                let child = std::mem::replace(child, self.children.tail.remove(self.len, 0));
-                let pivot = self.pivots.remove(self.len, 0);
-                self.len -= 1;
+    /// Removes every element that is also in `other`.
+    ///
+    /// See [`OkBTree::retain_intersection`] for how the trees are walked.
+    pub fn remove_all_in<const N: usize>(&mut self, other: &OkBTree<T, N>) {
+        self.retain_against(other, false);
+    }
 
-                let next_child = self.children.head.assume_init_mut();
+    /// Retains only the elements that are also in `other`.
+    ///
+    /// Both trees are walked in step, in `O(n + m)` time, and only the part of `other`
+    /// that overlaps with the span of `self` is visited. The tree is left untouched if
+    /// nothing is removed. Otherwise it is rebuilt from the remaining elements in `O(n)`,
+    /// with the old nodes freed as the new ones are filled, rather than modified in place.
+    pub fn retain_intersection<const N: usize>(&mut self, other: &OkBTree<T, N>) {
+        self.retain_against(other, true);
+    }
 
-                Self::merge_left(height, *child, pivot, next_child);
+    /// Keep only the elements for which being in `other` matches `present`.
+    fn retain_against<const N: usize>(&mut self, other: &OkBTree<T, N>, present: bool) {
+        /// Skip the elements of `others` less than `value`, returning whether the next one
+        /// is equal to it.
+        fn seek<T: Ord, const N: usize>(others: &mut Peekable<Range<'_, T, N>>, value: &T) -> bool {
+            while others.next_if(|other| *other < value).is_some() {}
+            others.peek().is_some_and(|other| *other == value)
+        }
 
-                if self.len < M / 2 {
-                    return Some(RemoveResult::Underflow(value));
-                } else {
-                    return Some(RemoveResult::Done(value));
-                }
-            },
-            Some(index) => index,
+        let (Some(first), Some(last)) = (self.first(), self.last()) else {
+            return;
         };
+        let overlap = other.range::<T, _>(first..=last);
 
-        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
-        // debug_assert_eq!(child.len, M / 2 - 1);
-
-        // check the right sibling first
-        if index + 1 < self.len {
-            unsafe {
-                let children = self.children.tail.as_mut_slice(self.len);
-                let [child, next_child] = children.get_unchecked_mut(index..index + 2) else {
-                    unreachable_unchecked()
-                };
-                let pivot = &mut pivots[index + 1];
+        let mut others = overlap.clone().peekable();
+        if self.iter().all(|value| seek(&mut others, value) == present) {
+            return;
+        }
 
-                if next_child.len > M / 2 {
-                    Self::rotate_left(height, child, pivot, next_child);
-                    return Some(RemoveResult::Done(value));
-                }
+        let old = mem::replace(self, OkBTree::with_policy(self.1));
+        let mut others = overlap.peekable();
+        for value in old {
+            if seek(&mut others, &value) == present {
+                self.insert_inner(value, InsertMode::Append);
             }
         }
+    }
 
-        let [prev_child, child] = {
-            match index.checked_sub(1) {
-                None => unsafe {
-                    [
-                        self.children.head.assume_init_mut(),
-                        self.children
-                            .tail
-                            .as_mut_slice(self.len)
-                            .get_unchecked_mut(0),
-                    ]
-                },
-                Some(index) => unsafe {
-                    let children = self.children.tail.as_mut_slice(self.len);
-                    let x: &mut [_; 2] = (children.get_unchecked_mut(index..index + 2))
-                        .try_into()
-                        .unwrap_unchecked();
-                    x.each_mut()
-                },
+    /// Removes each element for which `same_bucket` returns true, like [`Vec::dedup_by`].
+    ///
+    /// `same_bucket(a, b)` is passed each element `a` and the last element kept before it,
+    /// `b`, so only the first of each run is kept. With `|a, b| a == b`, this turns a tree
+    /// using [`DuplicatePolicy::AllowDuplicates`] into a set.
+    ///
+    /// The elements are walked once, and `same_bucket` is called once per element after
+    /// the first. As with [`OkBTree::retain_intersection`], the tree is left untouched if
+    /// nothing is removed, and is otherwise rebuilt from the kept elements in `O(n)`.
+    pub fn dedup_by<F: FnMut(&T, &T) -> bool>(&mut self, mut same_bucket: F) {
+        // the number of elements before the first one to remove
+        let mut kept = 1;
+        let mut iter = self.iter();
+        let Some(mut last) = iter.next() else { return };
+        loop {
+            let Some(value) = iter.next() else { return };
+            if same_bucket(value, last) {
+                break;
             }
-        };
-        let pivot = &mut pivots[index];
-
-        if prev_child.len > M / 2 {
-            Self::rotate_right(height, prev_child, pivot, child);
-            return Some(RemoveResult::Done(value));
+            last = value;
+            kept += 1;
         }
 
-        // we can only merge
+        let old = mem::replace(self, OkBTree::with_policy(self.1));
+        let mut old = old.into_iter();
+        for value in old.by_ref().take(kept - 1) {
+            self.insert_inner(value, InsertMode::Append);
+        }
+        // the last kept element is held back until the next one is found.
+        let mut last = old.next().unwrap();
+        old.next();
+        for value in old {
+            if !same_bucket(&value, &last) {
+                self.insert_inner(mem::replace(&mut last, value), InsertMode::Append);
+            }
+        }
+        self.insert_inner(last, InsertMode::Append);
+    }
 
-        let child = unsafe { self.children.tail.remove(self.len, index) };
-        let pivot = unsafe { self.pivots.remove(self.len, index) };
-        self.len -= 1;
+    /// Removes the `n` smallest elements, yielding them in ascending order.
+    ///
+    /// Elements are removed as the iterator advances. If it is dropped early,
+    /// the rest of the `n` elements are still removed.
+    ///
+    /// This is not a single-pass drain. Each element is taken with
+    /// [`OkBTree::remove_first`], which descends the edge of the tree and rebalances along it,
+    /// so popping `n` elements costs `O(n log len)`.
+    pub fn pop_first_n(&mut self, n: usize) -> PopFirstN<'_, T, M> {
+        PopFirstN { tree: self, n }
+    }
 
-        let prev_child = self.children.get_mut(self.len, index);
-        Self::merge_right(height, prev_child, pivot, *child);
+    /// Removes the `n` largest elements, yielding them in descending order.
+    ///
+    /// Elements are removed as the iterator advances. If it is dropped early,
+    /// the rest of the `n` elements are still removed.
+    ///
+    /// This is not a single-pass drain. Each element is taken with
+    /// [`OkBTree::remove_last`], which descends the edge of the tree and rebalances along it,
+    /// so popping `n` elements costs `O(n log len)`.
+    pub fn pop_last_n(&mut self, n: usize) -> PopLastN<'_, T, M> {
+        PopLastN { tree: self, n }
+    }
 
-        if self.len < M / 2 {
-            Some(RemoveResult::Underflow(value))
-        } else {
-            Some(RemoveResult::Done(value))
+    /// Insert `value`, following the tree's [`DuplicatePolicy`] if an equal element is
+    /// already present.
+    ///
+    /// Returns the element that did not end up in the tree: the replaced one under
+    /// [`DuplicatePolicy::Overwrite`], or `value` itself under [`DuplicatePolicy::Reject`].
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let policy = self.1;
+        match self.insert_inner(value, InsertMode::Search(policy)) {
+            Inserted::New(_) => None,
+            Inserted::Replaced(_, old) => Some(old),
+            Inserted::Kept(_, value) => value.filter(|_| policy == DuplicatePolicy::Reject),
         }
     }
 
-    fn merge_right(height: usize, lhs: &mut NodeArray<T, M>, pivot: T, rhs: NodeArray<T, M>) {
-        debug_assert_eq!(lhs.len + rhs.len + 1, M);
-        unsafe {
-            lhs.pivots.push(M / 2, pivot);
-            for (i, pivot) in rhs.pivots.into_iter(M / 2 - 1).enumerate() {
-                lhs.pivots.push(M / 2 + 1 + i, pivot);
-            }
-            if height > 1 {
-                lhs.children
-                    .tail
-                    .push(M / 2, rhs.children.head.assume_init_read());
-                for (i, child) in rhs.children.tail.into_iter(M / 2 - 1).enumerate() {
-                    lhs.children.tail.push(M / 2 + 1 + i, child);
-                }
-            }
-            lhs.len = M;
+    /// Get the element equal to `q`, inserting the one built by `f` if there is none.
+    ///
+    /// `f` is only called when nothing matches, so a set of `String`s probed with a `&str`
+    /// only allocates on a miss. The tree is descended once either way. It is a logic error
+    /// for `f` to return an element that is not equal to `q`, which is only checked with
+    /// debug assertions.
+    pub fn insert_with<Q>(&mut self, q: &Q, f: impl FnOnce() -> T) -> &T
+    where
+        Q: Comparable<T> + ?Sized,
+    {
+        if self.0.is_none() {
+            let value = f();
+            debug_assert!(
+                q.compare(&value).is_eq(),
+                "insert_with called with a value not equal to the probe"
+            );
+            return self.insert_first(value).value();
         }
+        self.finish_insert(|node, height| node.get_or_insert_with(height, q, f))
+            .value()
     }
 
-    fn merge_left(height: usize, lhs: NodeArray<T, M>, pivot: T, rhs: &mut NodeArray<T, M>) {
-        debug_assert_eq!(lhs.len + rhs.len + 1, M);
-        let x = std::mem::replace(rhs, lhs);
-        let (lhs, rhs) = (rhs, x);
+    /// Modify the element equal to `q` in place with `f`, moving it if its order changed.
+    ///
+    /// When the modified element still sorts between its neighbours, it stays where it is
+    /// and the tree is only searched once. Otherwise it is removed and reinserted. Under
+    /// [`DuplicatePolicy::AllowDuplicates`] the element is always removed and reinserted.
+    ///
+    /// Returns whether an element equal to `q` was found. If the modified element is equal
+    /// to another element in the tree, it is left out of the tree and returned in the error.
+    pub fn modify_key<Q>(
+        &mut self,
+        q: &Q,
+        f: impl FnOnce(&mut T),
+    ) -> Result<bool, KeyOrderViolation<T>>
+    where
+        Q: Comparable<T> + ?Sized,
+    {
+        if self.1 == DuplicatePolicy::AllowDuplicates {
+            let Some(mut value) = self.remove(q) else {
+                return Ok(false);
+            };
+            f(&mut value);
+            self.insert_inner(value, InsertMode::Search(self.1));
+            return Ok(true);
+        }
+
+        let Some(inner) = &mut self.0 else {
+            return Ok(false);
+        };
+        let height = inner.depth.get() - 1;
+        let root: *mut NodeArray<T, M> = &mut *inner.node;
+
+        // SAFETY: the root is borrowed mutably, so every node is readable and writable
+        // through `root`. The neighbours are found before modifying, while the tree is still
+        // in order, and no references into the tree are held across the write.
+        let target = unsafe {
+            let prev: Option<*const T> = (*root)
+                .upper_bound(height, Bound::Excluded(q))
+                .map(|p| p as _);
+            let next: Option<*const T> = (*root)
+                .lower_bound(height, Bound::Excluded(q))
+                .map(|n| n as _);
+
+            let Some((index, node)) = NodeArray::search_raw(root, height, Comp::from_comp(q))
+            else {
+                return Ok(false);
+            };
+            let target = DetachedArrayVec::get_ptr_mut(addr_of_mut!((*node).pivots), index);
 
-        unsafe {
-            lhs.pivots.push(M / 2 - 1, pivot);
-            for (i, pivot) in rhs.pivots.into_iter(M / 2).enumerate() {
-                lhs.pivots.push(M / 2 + i, pivot);
-            }
-            if height > 1 {
-                lhs.children
-                    .tail
-                    .push(M / 2 - 1, rhs.children.head.assume_init_read());
-                for (i, child) in rhs.children.tail.into_iter(M / 2).enumerate() {
-                    lhs.children.tail.push(M / 2 + i, child);
-                }
+            f(&mut *target);
+            let in_order = prev.map_or(true, |prev| *prev < *target)
+                && next.map_or(true, |next| *target < *next);
+            if in_order {
+                return Ok(true);
             }
-            lhs.len = M;
+            target
+        };
+
+        let value = self.remove_inner(&At { q, target }).unwrap();
+        if self.search(Comp::from_comp(&value)).is_some() {
+            return Err(KeyOrderViolation { value });
         }
+        self.insert_inner(value, InsertMode::Unique);
+        Ok(true)
     }
 
-    fn rotate_right(
-        height: usize,
-        lhs: &mut NodeArray<T, M>,
-        pivot: &mut T,
-        rhs: &mut NodeArray<T, M>,
-    ) {
-        debug_assert!(height > 0);
-        debug_assert!(lhs.len > M / 2);
-        debug_assert_eq!(rhs.len, M / 2 - 1);
-
-        if height == 1 {
-            // lhs and rhs are leaf nodes
-            unsafe {
-                let new = lhs.pivots.pop(lhs.len);
-                lhs.len -= 1;
+    /// Insert a value that is known not to be in the tree yet.
+    ///
+    /// This skips checking for an equal element to replace. It is a logic error for an
+    /// equal element to already be present, which is only checked with debug assertions.
+    pub fn insert_unique_unchecked(&mut self, value: T) {
+        debug_assert!(
+            self.get(&value).is_none(),
+            "insert_unique_unchecked called with a value already in the tree"
+        );
+        self.insert_inner(value, InsertMode::Unique);
+    }
 
-                let old = std::mem::replace(pivot, new);
-                rhs.pivots.insert(M / 2 - 1, 0, old);
+    /// Build a tree from an iterator of strictly increasing values.
+    ///
+    /// Every value is appended to the right edge of the tree without comparing it to the
+    /// existing elements. It is a logic error for the values to not be strictly increasing,
+    /// which is only checked with debug assertions.
+    pub fn from_sorted_unchecked<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::default();
+        for value in iter {
+            debug_assert!(
+                tree.last().map_or(true, |last| *last < value),
+                "from_sorted_unchecked called with values that are not strictly increasing"
+            );
+            tree.insert_inner(value, InsertMode::Append);
+        }
+        tree
+    }
 
-                rhs.len += 1;
+    /// Build a tree from an iterator of strictly increasing values.
+    ///
+    /// Like [`OkBTree::from_sorted_unchecked`], values are appended to the right edge of
+    /// the tree, but each is first checked to be less than the next. If one is not, the
+    /// position of the offending later value is returned.
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Result<Self, NotSorted> {
+        let mut tree = Self::default();
+        let mut iter = iter.into_iter().enumerate().peekable();
+        while let Some((_, value)) = iter.next() {
+            if let Some((index, next)) = iter.peek() {
+                if value >= *next {
+                    return Err(NotSorted { index: *index });
+                }
             }
-        } else {
-            // lhs and rhs are internal nodes
-            unsafe {
-                let child = lhs.children.tail.pop(lhs.len);
-                let new = lhs.pivots.pop(lhs.len);
-                lhs.len -= 1;
-
-                let old = std::mem::replace(pivot, new);
-                rhs.pivots.insert(M / 2 - 1, 0, old);
-                rhs.children.push_front(M / 2 - 1, child);
+            tree.insert_inner(value, InsertMode::Append);
+        }
+        Ok(tree)
+    }
 
-                rhs.len += 1;
-            }
+    fn insert_inner(&mut self, value: T, mode: InsertMode) -> Inserted<'_, T> {
+        if self.0.is_none() {
+            return self.insert_first(value);
         }
+        self.finish_insert(|node, height| node.insert(value, height, mode))
     }
 
-    fn rotate_left(
-        height: usize,
-        lhs: &mut NodeArray<T, M>,
-        pivot: &mut T,
-        rhs: &mut NodeArray<T, M>,
-    ) {
-        debug_assert!(height > 0);
-        debug_assert!(rhs.len > M / 2);
-        debug_assert_eq!(lhs.len, M / 2 - 1);
+    fn insert_first(&mut self, value: T) -> Inserted<'_, T> {
+        let mut pivots = DetachedArrayVec::new();
+        // SAFETY:
+        // pivots is currently uninit.
+        // M > 1 so there is capacity available.
+        unsafe { pivots.push(0, value) };
+        let inner = self.0.insert(BTreeInner {
+            depth: NonZeroUsize::new(1).unwrap(),
+            len: 1,
+            node: Box::new(NodeArray {
+                len: 1,
+                pivots,
+                children: Children::new(),
+            }),
+        });
+        // SAFETY: the root holds the one pivot just pushed.
+        Inserted::New(unsafe { inner.node.slot_ptr(0).as_ref() })
+    }
 
-        if height == 1 {
-            // lhs and rhs are leaf nodes
-            unsafe {
-                let new = rhs.pivots.remove(rhs.len, 0);
-                rhs.len -= 1;
+    /// Run `insert` on the root of a non-empty tree, growing the tree if the root split.
+    fn finish_insert(
+        &mut self,
+        insert: impl FnOnce(&mut NodeArray<T, M>, usize) -> InsertResult<T, M>,
+    ) -> Inserted<'_, T> {
+        let inner = self.0.as_mut().unwrap();
+        let (slot, found) = match insert(&mut inner.node, inner.depth.get() - 1) {
+            InsertResult::Propagate { pivot, right, slot } => {
+                let mut node = NodeArray {
+                    len: 1,
+                    pivots: DetachedArrayVec::new(),
+                    children: Children::new(),
+                };
 
-                let old = std::mem::replace(pivot, new);
-                lhs.pivots.push(M / 2 - 1, old);
-                lhs.len += 1;
+                // SAFETY:
+                // pivots and children are currently uninit.
+                // M > 1 so there is capacity available.
+                unsafe {
+                    node.pivots.push(0, pivot);
+                    node.children.tail.push(0, right);
+                }
+                let old = mem::replace(&mut inner.node, Box::new(node));
+                inner.node.children.head.write(old);
+                inner.depth = inner.depth.checked_add(1).unwrap();
+                inner.len += 1;
+
+                // the old root and the node split off from it are now children of the
+                // new root, and the pivot between them is its only pivot.
+                let slot = match slot {
+                    Slot::Here(i) => Slot::Below(inner.node.children.get(1, 0).slot_ptr(i)),
+                    Slot::Right(i) => Slot::Below(inner.node.children.get(1, 1).slot_ptr(i)),
+                    Slot::Pivot => Slot::Here(0),
+                    slot => slot,
+                };
+                (slot, None)
             }
-        } else {
-            // lhs and rhs are internal nodes
-            unsafe {
-                let child = rhs.children.pop_front(rhs.len);
-                let new = rhs.pivots.remove(rhs.len, 0);
-                rhs.len -= 1;
-
-                let old = std::mem::replace(pivot, new);
-                lhs.pivots.push(M / 2 - 1, old);
-                lhs.children.tail.push(M / 2 - 1, child);
-                lhs.len += 1;
+            InsertResult::Done(slot) => {
+                inner.len += 1;
+                (slot, None)
             }
+            InsertResult::Kept(value, slot) => (slot, Some(Err(value))),
+            InsertResult::Replaced(old, slot) => (slot, Some(Ok(old))),
+        };
+
+        let ptr = match slot {
+            Slot::Here(i) => inner.node.slot_ptr(i),
+            Slot::Below(ptr) => ptr,
+            Slot::Right(_) | Slot::Pivot => unreachable!("the root has no parent"),
+        };
+        // SAFETY: the tree is not changed again while the returned reference is live, and
+        // no node was moved by value after the pointer was taken.
+        let value = unsafe { ptr.as_ref() };
+        match found {
+            None => Inserted::New(value),
+            Some(Ok(old)) => Inserted::Replaced(value, old),
+            Some(Err(new)) => Inserted::Kept(value, new),
         }
     }
 }
 
-trait BinarySearch<K> {
-    /// Compare self to `key` and return their ordering.
-    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize>;
+/// What [`OkBTree::insert_inner`] did with a new element, along with the element in the
+/// tree that is equal to it.
+pub(crate) enum Inserted<'a, T> {
+    /// The new element was added.
+    New(&'a T),
+    /// The new element replaced an equal one, which is returned.
+    Replaced(&'a T, T),
+    /// An equal element was already present and kept. The new element is returned, if one
+    /// was built.
+    Kept(&'a T, Option<T>),
 }
 
-impl<K, Q: Comparable<K>> BinarySearch<K> for Comp<Q> {
-    fn binary_search(&self, pivots: &[K], _height: usize) -> Result<usize, usize> {
-        pivots.binary_search_by(|pivot| self.0.compare(pivot).reverse())
+impl<'a, T> Inserted<'a, T> {
+    fn value(self) -> &'a T {
+        match self {
+            Inserted::New(value) | Inserted::Replaced(value, _) | Inserted::Kept(value, _) => value,
+        }
     }
 }
 
-#[repr(transparent)]
-struct Comp<Q>(Q);
-
-impl<Q> Comp<Q> {
-    fn from_comp(q: &Q) -> &Self {
-        // SAFETY: transparent wrapper
-        unsafe { std::mem::transmute(q) }
+impl<T, const M: usize> Default for OkBTree<T, M> {
+    fn default() -> Self {
+        OkBTree(None, DuplicatePolicy::Overwrite)
     }
 }
 
-struct Last;
+impl<T: Clone, const M: usize> Clone for OkBTree<T, M> {
+    /// Copies the nodes one by one, so the clone has the same shape as the original and
+    /// takes `O(n)` time.
+    fn clone(&self) -> Self {
+        let inner = self.0.as_ref().map(|inner| BTreeInner {
+            depth: inner.depth,
+            len: inner.len,
+            node: OwnedNode::clone_from_ref(NodeRef {
+                height: inner.depth.get() - 1,
+                node: &inner.node,
+            })
+            .into_inner(),
+        });
+        OkBTree(inner, self.1)
+    }
+}
 
-impl<K> BinarySearch<K> for Last {
-    #[inline]
-    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize> {
-        if height == 0 && !pivots.is_empty() {
-            Ok(pivots.len() - 1)
-        } else {
-            Err(pivots.len())
+impl<T: Ord, const M: usize> Extend<T> for OkBTree<T, M> {
+    /// Inserts each item in turn, so the tree's [`DuplicatePolicy`] decides which of any
+    /// equal items are kept.
+    ///
+    /// While the items stay above every element in the tree, they are appended along its
+    /// right edge without searching, as in [`OkBTree::from_sorted_unchecked`]. So ascending
+    /// runs cost one comparison each, and any other item falls back to [`OkBTree::insert`].
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            let append = match self.last() {
+                Some(last) if self.1 == DuplicatePolicy::AllowDuplicates => *last <= value,
+                Some(last) => *last < value,
+                None => true,
+            };
+            if append {
+                self.insert_inner(value, InsertMode::Append);
+            } else {
+                self.insert(value);
+            }
         }
     }
 }
 
-struct First;
-
-impl<K> BinarySearch<K> for First {
-    #[inline]
-    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize> {
-        if height == 0 && !pivots.is_empty() {
-            Ok(0)
-        } else {
-            Err(0)
-        }
+impl<'a, T: Ord + Copy + 'a, const M: usize> Extend<&'a T> for OkBTree<T, M> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
     }
 }
 
-pub struct OkBTree<T>(Option<BTreeInner<T>>);
+impl<T: Ord, const M: usize> FromIterator<T> for OkBTree<T, M> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = OkBTree::default();
+        tree.extend(iter);
+        tree
+    }
+}
 
-pub struct BTreeInner<T> {
-    depth: NonZeroUsize,
-    node: Box<NodeArray<T, M>>,
+/// Iterator returned by [`OkBTree::remove_all`].
+pub struct RemoveAll<'a, 'q, T: Ord, const M: usize, Q: Comparable<T> + ?Sized> {
+    tree: &'a mut OkBTree<T, M>,
+    q: &'q Q,
 }
 
-impl<T: std::fmt::Debug> std::fmt::Debug for OkBTree<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(node) = &self.0 {
-            NodeArrayFmt {
-                height: node.depth.get() - 1,
-                array: &node.node,
-            }
-            .fmt(f)
-        } else {
-            f.debug_list().finish()
-        }
+impl<T: Ord, const M: usize, Q: Comparable<T> + ?Sized> Iterator for RemoveAll<'_, '_, T, M, Q> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let target: *const T = self.tree.get_all(self.q).next()?;
+        self.tree.remove_inner(&FirstAt { q: self.q, target })
     }
 }
 
-impl<T> Drop for OkBTree<T> {
+impl<T: Ord, const M: usize, Q: Comparable<T> + ?Sized> std::iter::FusedIterator
+    for RemoveAll<'_, '_, T, M, Q>
+{
+}
+
+impl<T: Ord, const M: usize, Q: Comparable<T> + ?Sized> Drop for RemoveAll<'_, '_, T, M, Q> {
     fn drop(&mut self) {
-        if let Some(mut inner) = self.0.take() {
-            // SAFETY: height is set correctly.
-            unsafe { inner.node.drop_inner(inner.depth.get() - 1) }
-        }
+        self.for_each(drop);
     }
 }
 
-struct NodeArrayFmt<'a, T, const M: usize> {
-    height: usize,
-    array: &'a NodeArray<T, M>,
+/// Iterator returned by [`OkBTree::pop_first_n`].
+pub struct PopFirstN<'a, T: Ord, const M: usize> {
+    tree: &'a mut OkBTree<T, M>,
+    n: usize,
 }
 
-impl<T: std::fmt::Debug, const M: usize> std::fmt::Debug for NodeArrayFmt<'_, T, M> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut list = f.debug_list();
-
-        // SAFETY: len pivots are init
-        let pivots = unsafe { self.array.pivots.as_slice(self.array.len) };
-
-        if self.height == 0 {
-            list.entries(pivots);
-        } else {
-            debug_assert_ne!(self.array.len, 0);
+impl<T: Ord, const M: usize> Iterator for PopFirstN<'_, T, M> {
+    type Item = T;
 
-            list.entry(&NodeArrayFmt {
-                height: self.height - 1,
-                // SAFETY: head is always init when height > 0
-                array: unsafe { self.array.children.head.assume_init_ref() },
-            });
-
-            // SAFETY: len children are init
-            let tail = unsafe { self.array.children.tail.as_slice(self.array.len) };
-            for (p, c) in std::iter::zip(pivots, tail) {
-                list.entry(p);
-                list.entry(&NodeArrayFmt {
-                    height: self.height - 1,
-                    array: c,
-                });
-            }
+    fn next(&mut self) -> Option<T> {
+        self.n = self.n.checked_sub(1)?;
+        let value = self.tree.remove_first();
+        if value.is_none() {
+            self.n = 0;
         }
-        list.finish()
+        value
     }
-}
 
-enum InsertResult<T, const M: usize> {
-    Propagate {
-        pivot: T,
-        right: Box<NodeArray<T, M>>,
-    },
-    Done,
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.n))
+    }
 }
 
-enum RemoveResult<T> {
-    Underflow(T),
-    Done(T),
-}
+impl<T: Ord, const M: usize> std::iter::FusedIterator for PopFirstN<'_, T, M> {}
 
-impl<T> RemoveResult<T> {
-    fn map<U>(self, f: impl FnOnce(T) -> U) -> RemoveResult<U> {
-        match self {
-            RemoveResult::Underflow(value) => RemoveResult::Underflow(f(value)),
-            RemoveResult::Done(value) => RemoveResult::Done(f(value)),
-        }
+impl<T: Ord, const M: usize> Drop for PopFirstN<'_, T, M> {
+    fn drop(&mut self) {
+        self.for_each(drop);
     }
 }
 
-impl<T> OkBTree<T> {
-    pub const fn new() -> Self {
-        OkBTree(None)
-    }
+/// Iterator returned by [`OkBTree::pop_last_n`].
+pub struct PopLastN<'a, T: Ord, const M: usize> {
+    tree: &'a mut OkBTree<T, M>,
+    n: usize,
 }
 
-impl<T: Ord> OkBTree<T> {
-    fn search<B: BinarySearch<T>>(&self, b: &B) -> Option<&T> {
-        let inner = self.0.as_ref()?;
-        unsafe {
-            let (index, child) = NodeArray::<T, M>::search_raw(
-                &*inner.node as *const NodeArray<T, M> as *mut _,
-                inner.depth.get() - 1,
-                b,
-            )?;
+impl<T: Ord, const M: usize> Iterator for PopLastN<'_, T, M> {
+    type Item = T;
 
-            let pivots = addr_of!((*child).pivots);
-            let value = DetachedArrayVec::get_ptr_mut(pivots.cast_mut(), index);
-            Some(&*value)
+    fn next(&mut self) -> Option<T> {
+        self.n = self.n.checked_sub(1)?;
+        let value = self.tree.remove_last();
+        if value.is_none() {
+            self.n = 0;
         }
+        value
     }
 
-    pub fn get<Q: Comparable<T>>(&self, q: &Q) -> Option<&T> {
-        self.search(Comp::from_comp(q))
-    }
-    pub fn last(&self) -> Option<&T> {
-        self.search(&Last)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.n))
     }
-    pub fn first(&self) -> Option<&T> {
-        self.search(&First)
+}
+
+impl<T: Ord, const M: usize> std::iter::FusedIterator for PopLastN<'_, T, M> {}
+
+impl<T: Ord, const M: usize> Drop for PopLastN<'_, T, M> {
+    fn drop(&mut self) {
+        self.for_each(drop);
     }
-
-    fn remove_inner<B: BinarySearch<T>>(&mut self, b: &B) -> Option<T> {
-        if let Some(inner) = &mut self.0 {
-            if inner.node.len == 0 {
-                return None;
-            };
-            match inner.node.remove(inner.depth.get() - 1, b)? {
-                RemoveResult::Done(val) => Some(val),
-                RemoveResult::Underflow(val) => {
-                    if inner.node.len == 0 && inner.depth.get() > 1 {
-                        inner.node = unsafe { inner.node.children.head.assume_init_read() };
-                        inner.depth = NonZeroUsize::new(inner.depth.get() - 1).unwrap();
-                    }
-
-                    Some(val)
-                }
-            }
-        } else {
-            None
-        }
-    }
-
-    pub fn remove_last(&mut self) -> Option<T> {
-        self.remove_inner(&Last)
-    }
-
-    pub fn remove_first(&mut self) -> Option<T> {
-        self.remove_inner(&First)
-    }
-
-    pub fn remove<Q: Comparable<T>>(&mut self, q: &Q) -> Option<T> {
-        self.remove_inner(Comp::from_comp(q))
-    }
-
-    pub fn insert(&mut self, value: T) {
-        if let Some(mut inner) = self.0.take() {
-            match inner.node.insert(value, inner.depth.get() - 1) {
-                InsertResult::Propagate { pivot, right } => {
-                    let depth = inner.depth.checked_add(1).unwrap();
-                    let mut node = NodeArray {
-                        len: 1,
-                        pivots: DetachedArrayVec::new(),
-                        children: Children::new(),
-                    };
-
-                    // SAFETY:
-                    // pivots and children are currently uninit.
-                    // M > 1 so there is capacity available.
-                    unsafe {
-                        node.pivots.push(0, pivot);
-                        node.children.head.write(inner.node);
-                        node.children.tail.push(0, right);
-                    }
-
-                    self.0 = Some(BTreeInner {
-                        depth,
-                        node: Box::new(node),
-                    })
-                }
-                InsertResult::Done => {
-                    self.0 = Some(inner);
-                }
-            }
-        } else {
-            let mut pivots = DetachedArrayVec::new();
-            // SAFETY:
-            // pivots is currently uninit.
-            // M > 1 so there is capacity available.
-            unsafe { pivots.push(0, value) };
-            self.0 = Some(BTreeInner {
-                depth: NonZeroUsize::new(1).unwrap(),
-                node: Box::new(NodeArray {
-                    len: 1,
-                    pivots,
-                    children: Children::new(),
-                }),
-            });
-        }
-    }
-}
-
-impl<T> Default for OkBTree<T> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+}
 
 // #[inline(never)]
 // pub fn insert_i32(x: &mut OkBTree<i32>) {
@@ -789,7 +904,7 @@ impl<T> Default for OkBTree<T> {
 
 #[cfg(test)]
 mod test {
-    use crate::OkBTree;
+    use crate::{DuplicatePolicy, KeyOrderViolation, OkBTree};
 
     #[test]
     fn get() {
@@ -841,4 +956,839 @@ mod test {
             assert_eq!(btree.remove(&i), Some(i));
         }
     }
+    #[test]
+    fn remove_owned() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert((i, counter.clone()));
+        }
+
+        // merges move the rest of the elements between nodes
+        for i in (0..1000).step_by(2) {
+            assert_eq!(btree.remove(&(i, counter.clone())).map(|v| v.0), Some(i));
+        }
+        assert_eq!(Rc::strong_count(&counter), 501);
+
+        drop(btree);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn insert_unchecked() {
+        let btree = OkBTree::<i32>::from_sorted_unchecked(0..1000);
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i), Some(&i));
+        }
+        assert_eq!(btree.first(), Some(&0));
+        assert_eq!(btree.last(), Some(&999));
+
+        let mut btree = OkBTree::new();
+        for i in (0..1000).rev().step_by(2) {
+            btree.insert_unique_unchecked(i);
+        }
+        for i in (0..1000).step_by(2) {
+            btree.insert_unique_unchecked(i);
+        }
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        use crate::NotSorted;
+
+        let btree = OkBTree::<i32>::from_sorted_iter(0..1000).unwrap();
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i), Some(&i));
+        }
+
+        let err = OkBTree::<i32>::from_sorted_iter((0..100).chain(99..200)).err();
+        assert_eq!(err, Some(NotSorted { index: 100 }));
+        let err = OkBTree::<i32>::from_sorted_iter([2, 1]).err();
+        assert_eq!(err, Some(NotSorted { index: 1 }));
+        assert!(OkBTree::<i32>::from_sorted_iter([])
+            .unwrap()
+            .first()
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "not strictly increasing"]
+    fn from_sorted_unchecked_debug_checked() {
+        OkBTree::<i32>::from_sorted_unchecked([1, 3, 2]);
+    }
+
+    #[test]
+    fn pop_n() {
+        let mut btree = OkBTree::new();
+        for i in 0..100 {
+            btree.insert(i);
+        }
+
+        assert!(btree.pop_first_n(10).eq(0..10));
+        assert!(btree.pop_last_n(10).eq((90..100).rev()));
+
+        // dropping early still removes all n
+        assert_eq!(btree.pop_first_n(10).next(), Some(10));
+        assert_eq!(btree.first(), Some(&20));
+
+        // asking for more than there are stops at empty
+        assert!(btree.pop_last_n(1000).eq((20..90).rev()));
+        assert!(btree.first().is_none());
+    }
+
+    #[test]
+    fn remove_all_in() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+        let mut other = OkBTree::new();
+        for i in (-500..2000).step_by(3) {
+            other.insert(i);
+        }
+
+        btree.remove_all_in(&other);
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i).is_some(), i % 3 != 1, "{i}");
+        }
+
+        btree.remove_all_in(&OkBTree::new());
+        assert_eq!(btree.first(), Some(&0));
+    }
+
+    #[test]
+    fn retain_intersection() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+        let mut other = OkBTree::new();
+        for i in (500..2000).step_by(3) {
+            other.insert(i);
+        }
+
+        btree.retain_intersection(&other);
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i).is_some(), i >= 500 && i % 3 == 2, "{i}");
+        }
+
+        btree.retain_intersection(&OkBTree::new());
+        assert!(btree.first().is_none());
+    }
+
+    #[test]
+    fn retain_against() {
+        let mut btree: OkBTree<i32, 2> = (0..100).collect();
+        let root = |btree: &OkBTree<i32, 2>| -> *const _ { &*btree.0.as_ref().unwrap().node };
+
+        // nothing to remove, so the nodes are left as they are
+        let before = root(&btree);
+        btree.retain_intersection(&(-10..200).collect::<OkBTree<i32>>());
+        btree.remove_all_in(&(100..200).collect::<OkBTree<i32>>());
+        assert_eq!(root(&btree), before);
+
+        let mut multiset = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..60 {
+            multiset.insert(i % 6);
+        }
+        let odds: OkBTree<i32> = [1, 3, 5].into_iter().collect();
+        let mut evens = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        evens.extend(multiset.iter());
+        multiset.retain_intersection(&odds);
+        evens.remove_all_in(&odds);
+        assert_eq!(multiset.len(), 30);
+        assert_eq!(evens.len(), 30);
+        assert!(multiset.iter().all(|i| i % 2 == 1));
+        assert!(evens.iter().all(|i| i % 2 == 0));
+        assert_eq!(multiset.validate(), Ok(()));
+        assert_eq!(evens.validate(), Ok(()));
+        assert_eq!(multiset.policy(), DuplicatePolicy::AllowDuplicates);
+    }
+
+    #[test]
+    fn dedup_by() {
+        let mut multiset = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..300 {
+            multiset.insert(i % 100);
+        }
+
+        // nothing to remove, so the nodes are left as they are
+        let mut set: OkBTree<i32, 2> = (0..100).collect();
+        let before: *const _ = &*set.0.as_ref().unwrap().node;
+        let mut calls = 0;
+        set.dedup_by(|a, b| {
+            calls += 1;
+            a == b
+        });
+        assert_eq!(calls, 99);
+        assert_eq!(&*set.0.as_ref().unwrap().node as *const _, before);
+
+        calls = 0;
+        multiset.dedup_by(|a, b| {
+            calls += 1;
+            a == b
+        });
+        assert_eq!(calls, 299);
+        assert!(multiset.iter().eq(set.iter()));
+        assert_eq!(multiset.validate(), Ok(()));
+        assert_eq!(multiset.policy(), DuplicatePolicy::AllowDuplicates);
+
+        // each element is compared with the last one kept, not the one before it
+        multiset.dedup_by(|a, b| a - b < 10);
+        assert!(multiset.iter().eq(&[0, 10, 20, 30, 40, 50, 60, 70, 80, 90]));
+        assert_eq!(multiset.validate(), Ok(()));
+
+        let mut empty = OkBTree::<i32>::new();
+        empty.dedup_by(|_, _| unreachable!());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn visit_nodes() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+
+        let mut values = vec![];
+        let mut heights = vec![];
+        btree.visit_nodes(|node| {
+            assert!(node.pivots().windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(node.is_leaf(), node.child_count() == 0);
+            if !node.is_leaf() {
+                assert_eq!(node.child_count(), node.pivots().len() + 1);
+            }
+            values.extend_from_slice(node.pivots());
+            heights.push(node.height());
+        });
+
+        values.sort();
+        assert!(values.into_iter().eq(0..1000));
+
+        // pre-order: the root comes first and is the tallest
+        assert!(heights.iter().all(|&h| h <= heights[0]));
+        assert_eq!(heights.last(), Some(&0));
+
+        OkBTree::<i32>::new().visit_nodes(|_| panic!("empty trees have no nodes"));
+    }
+
+    #[test]
+    fn from_iter_extend() {
+        let mut btree: OkBTree<i32, 2> = (0..100).rev().collect();
+        assert_eq!(btree.len(), 100);
+        assert!(btree.iter().copied().eq(0..100));
+        assert_eq!(btree.validate(), Ok(()));
+
+        btree.extend(&[50, 150, 200]);
+        btree.extend(vec![-1, 100]);
+        assert_eq!(btree.len(), 104);
+        assert_eq!(btree.first(), Some(&-1));
+        assert_eq!(btree.last(), Some(&200));
+
+        let mut multiset = OkBTree::<i32>::with_policy(DuplicatePolicy::AllowDuplicates);
+        multiset.extend([1, 1, 2]);
+        assert_eq!(multiset.len(), 3);
+    }
+
+    #[test]
+    fn get_all_remove_all() {
+        use std::cmp::Ordering;
+
+        // ordered by key alone, so equal elements can be told apart by tag.
+        #[derive(Debug, Clone, Copy)]
+        struct Tagged(i32, i32);
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Tagged {}
+        impl PartialOrd for Tagged {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Tagged {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        let tags = |iter: &mut dyn Iterator<Item = Tagged>| iter.map(|t| t.1).collect::<Vec<_>>();
+
+        let mut multiset = OkBTree::<Tagged, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for tag in 0..40 {
+            for key in 0..10 {
+                multiset.insert(Tagged(key, tag));
+            }
+        }
+        assert_eq!(
+            tags(&mut multiset.get_all(&Tagged(5, -1)).copied()),
+            (0..40).collect::<Vec<_>>()
+        );
+        assert_eq!(multiset.get_all(&Tagged(10, 0)).count(), 0);
+
+        assert_eq!(
+            tags(&mut multiset.remove_all(&Tagged(5, -1))),
+            (0..40).collect::<Vec<_>>()
+        );
+        assert_eq!(multiset.get_all(&Tagged(5, -1)).count(), 0);
+        assert_eq!(multiset.len(), 360);
+        assert_eq!(multiset.validate(), Ok(()));
+
+        // the rest are removed when the iterator is dropped
+        assert_eq!(
+            multiset.remove_all(&Tagged(3, -1)).next().map(|t| t.1),
+            Some(0)
+        );
+        assert_eq!(multiset.get_all(&Tagged(3, -1)).count(), 0);
+        assert_eq!(multiset.len(), 320);
+        assert_eq!(multiset.remove_all(&Tagged(3, -1)).count(), 0);
+        assert_eq!(multiset.validate(), Ok(()));
+        for key in [0, 1, 2, 4, 6, 7, 8, 9] {
+            assert_eq!(
+                tags(&mut multiset.get_all(&Tagged(key, -1)).copied()),
+                (0..40).collect::<Vec<_>>()
+            );
+        }
+
+        // a set holds at most one of each
+        let mut set: OkBTree<i32> = (0..10).collect();
+        assert!(set.get_all(&5).eq(&[5]));
+        assert!(set.remove_all(&5).eq([5]));
+        assert_eq!(set.len(), 9);
+    }
+
+    #[test]
+    fn extend_runs() {
+        use std::{
+            cmp::Ordering,
+            sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        };
+
+        // counts the comparisons made between any two
+        static COMPARISONS: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug, PartialEq, Eq)]
+        struct Counted(i32);
+        impl PartialOrd for Counted {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Counted {
+            fn cmp(&self, other: &Self) -> Ordering {
+                COMPARISONS.fetch_add(1, Relaxed);
+                self.0.cmp(&other.0)
+            }
+        }
+
+        // an ascending run is checked against the last element only
+        let mut btree = OkBTree::<Counted, 2>::default();
+        btree.extend((0..1000).map(Counted));
+        assert_eq!(COMPARISONS.load(Relaxed), 999);
+        assert_eq!(btree.validate(), Ok(()));
+
+        // runs broken by items that need a search, in both directions
+        let items = (2000..2500)
+            .chain([5, 1500, -1, 3000, 3000])
+            .chain(4000..4200);
+        let mut expected = OkBTree::<i32, 2>::default();
+        let mut found = expected.clone();
+        found.extend(0..1000);
+        found.extend(items.clone());
+        for i in (0..1000).chain(items) {
+            expected.insert(i);
+        }
+        assert_eq!(found.validate(), Ok(()));
+        assert!(found.iter().eq(expected.iter()));
+
+        // equal items continue a run of a multiset, and are kept in order
+        let mut multiset = OkBTree::<(i32, i32), 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        multiset.extend((0..300).map(|i| (i / 3, 0)));
+        multiset.extend([(99, 1), (50, 0), (99, 0)]);
+        assert_eq!(multiset.validate(), Ok(()));
+        assert_eq!(multiset.len(), 303);
+        assert!(multiset
+            .range((99, 0)..)
+            .eq(&[(99, 0), (99, 0), (99, 0), (99, 0), (99, 1)]));
+    }
+
+    #[test]
+    fn clone() {
+        use std::{cell::Cell, rc::Rc};
+
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            for i in 0..500 {
+                btree.insert((i * 7919) % 500);
+            }
+            for i in (0..500).step_by(3) {
+                btree.remove(&i);
+            }
+
+            let mut copy = btree.clone();
+            assert_eq!(copy.validate(), Ok(()));
+            let (mut nodes, mut copied_nodes) = (vec![], vec![]);
+            btree.visit_nodes(|node| nodes.push(node.to_desc()));
+            copy.visit_nodes(|node| copied_nodes.push(node.to_desc()));
+            assert_eq!(nodes, copied_nodes);
+
+            copy.insert(1000);
+            copy.remove(&1);
+            assert_eq!(btree.len(), 333);
+            assert!(btree.contains(&1) && !btree.contains(&1000));
+            assert_eq!(copy.len(), 333);
+
+            assert_eq!(OkBTree::<i32, M>::default().clone().first(), None);
+        }
+
+        check::<2>();
+        check::<8>();
+
+        // the policy is copied too
+        let mut multiset = OkBTree::<i32>::with_policy(DuplicatePolicy::AllowDuplicates).clone();
+        multiset.insert(1);
+        multiset.insert(1);
+        assert_eq!(multiset.len(), 2);
+
+        // an element that panics on the nth clone, to check the partial copy is dropped
+        struct Flaky<'a>(i32, Rc<()>, &'a Cell<usize>);
+        impl Clone for Flaky<'_> {
+            fn clone(&self) -> Self {
+                let left = self.2.get();
+                assert!(left > 0, "out of clones");
+                self.2.set(left - 1);
+                Flaky(self.0, self.1.clone(), self.2)
+            }
+        }
+        impl PartialEq for Flaky<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Flaky<'_> {}
+        impl PartialOrd for Flaky<'_> {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Flaky<'_> {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let rc = Rc::new(());
+        let clones = Cell::new(usize::MAX);
+        let btree: OkBTree<Flaky, 2> =
+            OkBTree::from_sorted_unchecked((0..50).map(|i| Flaky(i, rc.clone(), &clones)));
+        clones.set(25);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| btree.clone()));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&rc), 51);
+    }
+
+    #[test]
+    fn fanout() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            for i in 0..1000 {
+                btree.insert(i);
+            }
+            for i in 0..1000 {
+                assert_eq!(btree.get(&i), Some(&i));
+            }
+            for i in (0..1000).step_by(2) {
+                assert_eq!(btree.remove(&i), Some(i));
+            }
+            for i in (1..1000).step_by(2) {
+                assert_eq!(btree.remove_first(), Some(i));
+            }
+            assert!(btree.first().is_none());
+        }
+
+        check::<2>();
+        check::<4>();
+        check::<16>();
+    }
+
+    #[test]
+    fn duplicate_policy() {
+        // compares on the first field only
+        #[derive(Debug, PartialEq)]
+        struct Entry(i32, &'static str);
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Entry {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        impl Eq for Entry {}
+        impl equivalent::Equivalent<Entry> for i32 {
+            fn equivalent(&self, key: &Entry) -> bool {
+                *self == key.0
+            }
+        }
+        impl equivalent::Comparable<Entry> for i32 {
+            fn compare(&self, key: &Entry) -> std::cmp::Ordering {
+                self.cmp(&key.0)
+            }
+        }
+
+        let mut btree = OkBTree::<Entry, 4>::default();
+        assert_eq!(btree.policy(), DuplicatePolicy::Overwrite);
+        assert_eq!(btree.insert(Entry(1, "a")), None);
+        assert_eq!(btree.insert(Entry(1, "b")), Some(Entry(1, "a")));
+        assert_eq!(btree.get(&1), Some(&Entry(1, "b")));
+
+        let mut btree = OkBTree::<Entry, 4>::with_policy(DuplicatePolicy::KeepExisting);
+        assert_eq!(btree.insert(Entry(1, "a")), None);
+        assert_eq!(btree.insert(Entry(1, "b")), None);
+        assert_eq!(btree.get(&1), Some(&Entry(1, "a")));
+
+        let mut btree = OkBTree::<Entry, 4>::with_policy(DuplicatePolicy::Reject);
+        assert_eq!(btree.insert(Entry(1, "a")), None);
+        assert_eq!(btree.insert(Entry(1, "b")), Some(Entry(1, "b")));
+        assert_eq!(btree.get(&1), Some(&Entry(1, "a")));
+
+        let mut btree = OkBTree::<i32, 4>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..100 {
+            assert_eq!(btree.insert(i % 10), None);
+        }
+        for i in 0..10 {
+            for _ in 0..5 {
+                assert_eq!(btree.remove(&i), Some(i));
+            }
+        }
+        for i in 0..50 {
+            assert_eq!(btree.remove_first(), Some(i / 5));
+        }
+        assert!(btree.first().is_none());
+    }
+
+    #[test]
+    fn insert_with() {
+        let mut btree = OkBTree::<String>::new();
+        let mut built = 0;
+        for word in ["b", "a", "b", "c", "a", "b"] {
+            let value = btree.insert_with(word, || {
+                built += 1;
+                word.to_owned()
+            });
+            assert_eq!(value, word);
+        }
+        assert_eq!(built, 3);
+        assert_eq!(btree.first().map(String::as_str), Some("a"));
+        assert_eq!(btree.last().map(String::as_str), Some("c"));
+    }
+
+    #[test]
+    fn insert_with_descends_once() {
+        use std::cell::Cell;
+
+        // counts the comparisons made with it
+        struct Probe<'a>(i32, &'a Cell<usize>);
+        impl equivalent::Equivalent<i32> for Probe<'_> {
+            fn equivalent(&self, key: &i32) -> bool {
+                self.0 == *key
+            }
+        }
+        impl equivalent::Comparable<i32> for Probe<'_> {
+            fn compare(&self, key: &i32) -> std::cmp::Ordering {
+                self.1.set(self.1.get() + 1);
+                self.0.cmp(key)
+            }
+        }
+
+        fn check<const M: usize>() {
+            let mut btree: OkBTree<i32, M> = (0..1000).map(|i| i * 2).collect();
+            let (get, insert) = (Cell::new(0), Cell::new(0));
+            let mut checked = 0;
+            for i in 0..2000 {
+                // the same search as a lookup, whether it hits or misses. A miss also
+                // checks the built value against the probe with debug assertions.
+                let hit = btree.get(&Probe(i, &get)).is_some();
+                assert_eq!(*btree.insert_with(&Probe(i, &insert), || i), i);
+                checked += usize::from(!hit && cfg!(debug_assertions));
+                assert_eq!(insert.get(), get.get() + checked);
+            }
+            assert_eq!(btree.len(), 2000);
+            assert_eq!(btree.validate(), Ok(()));
+            assert!(btree.iter().copied().eq(0..2000));
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn modify_key() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            for i in 0..50 {
+                btree.insert(i * 10);
+            }
+            // stays between its neighbours
+            assert_eq!(btree.modify_key(&250, |v| *v += 5), Ok(true));
+            // moves past its neighbours
+            assert_eq!(btree.modify_key(&100, |v| *v = 1000), Ok(true));
+            assert_eq!(btree.modify_key(&0, |v| *v = 333), Ok(true));
+            // lands on another element
+            assert_eq!(
+                btree.modify_key(&300, |v| *v = 400),
+                Err(KeyOrderViolation { value: 400 })
+            );
+            assert_eq!(btree.modify_key(&7, |v| *v = 8), Ok(false));
+
+            let mut expected: Vec<i32> = (0..50).map(|i| i * 10).collect();
+            expected.retain(|&v| ![0, 100, 250, 300].contains(&v));
+            expected.extend([255, 333, 1000]);
+            expected.sort();
+            let mut actual = vec![];
+            while let Some(v) = btree.remove_first() {
+                actual.push(v);
+            }
+            assert_eq!(actual, expected);
+        }
+
+        check::<2>();
+        check::<8>();
+
+        let mut multiset = OkBTree::<i32>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for v in [1, 2, 2, 3] {
+            multiset.insert(v);
+        }
+        assert_eq!(multiset.modify_key(&1, |v| *v = 2), Ok(true));
+        assert_eq!(multiset.get_full(&3), Some((3, &3)));
+    }
+
+    #[test]
+    fn len() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            assert!(btree.is_empty());
+            for i in 0..200 {
+                btree.insert(i);
+                btree.insert(i);
+                assert_eq!(btree.len(), i as usize + 1);
+            }
+            for i in (0..200).step_by(2) {
+                btree.remove(&i);
+                btree.remove(&i);
+            }
+            assert_eq!(btree.len(), 100);
+            btree.remove_first();
+            btree.remove_last();
+            assert_eq!(btree.len(), 98);
+
+            let evens = OkBTree::<i32, M>::from_sorted_unchecked((0..100).map(|i| i * 2));
+            assert_eq!(evens.len(), 100);
+            btree.remove_all_in(&OkBTree::<i32, M>::from_sorted_unchecked(0..50));
+            assert_eq!(btree.len(), 74);
+            btree.retain_intersection(&evens);
+            assert_eq!(btree.len(), 0);
+            assert!(btree.is_empty());
+
+            for policy in [
+                DuplicatePolicy::KeepExisting,
+                DuplicatePolicy::Reject,
+                DuplicatePolicy::AllowDuplicates,
+            ] {
+                let mut btree = OkBTree::<i32, M>::with_policy(policy);
+                for i in [1, 2, 1, 2] {
+                    btree.insert(i);
+                }
+                let expected = if policy == DuplicatePolicy::AllowDuplicates {
+                    4
+                } else {
+                    2
+                };
+                assert_eq!(btree.len(), expected);
+            }
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn clear() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut btree = OkBTree::<_, 2>::with_policy(DuplicatePolicy::Reject);
+        for i in 0..1000 {
+            btree.insert((i, counter.clone()));
+        }
+        btree.clear();
+        assert_eq!(Rc::strong_count(&counter), 1);
+        assert!(btree.is_empty());
+        assert_eq!(btree.first(), None);
+
+        btree.insert((1, counter.clone()));
+        assert!(btree.insert((1, counter.clone())).is_some());
+        assert_eq!(btree.len(), 1);
+        drop(btree);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn contains() {
+        let mut btree = OkBTree::<i32, 2>::default();
+        assert!(!btree.contains(&0));
+        for i in 0..100 {
+            btree.insert(i * 2);
+        }
+        for i in 0..100 {
+            assert!(btree.contains(&(i * 2)));
+            assert!(!btree.contains(&(i * 2 + 1)));
+        }
+
+        let mut words = OkBTree::<std::borrow::Cow<'_, str>>::new();
+        words.insert("a".into());
+        words.insert("b".into());
+        assert!(words.contains("a"));
+        assert!(!words.contains("c"));
+    }
+
+    #[test]
+    fn unsized_probe() {
+        use std::ops::Bound;
+
+        let mut btree = OkBTree::<String, 2>::default();
+        for word in ["pear", "apple", "fig", "plum", "kiwi", "lime"] {
+            btree.insert(word.to_owned());
+        }
+        assert_eq!(btree.get("fig").map(String::as_str), Some("fig"));
+        assert_eq!(btree.get("date"), None);
+        assert_eq!(btree.get_full("kiwi").map(|(rank, _)| rank), Some(2));
+        assert_eq!(
+            btree.first_in_range::<str, _>((Bound::Excluded("kiwi"), Bound::Unbounded)),
+            Some(&"lime".to_owned())
+        );
+        assert_eq!(btree.remove("plum").as_deref(), Some("plum"));
+        assert_eq!(btree.take("plum"), None);
+    }
+
+    #[test]
+    fn get_full() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            for i in 0..500 {
+                btree.insert(i * 2);
+            }
+            for i in 0..500 {
+                assert_eq!(btree.get_full(&(i * 2)), Some((i as usize, &(i * 2))));
+                assert_eq!(btree.get_full(&(i * 2 + 1)), None);
+            }
+            assert_eq!(btree.take(&10), Some(10));
+            assert_eq!(btree.take(&10), None);
+            assert_eq!(btree.get_full(&12), Some((5, &12)));
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn first_last_in_range() {
+        use std::ops::{Bound, RangeBounds};
+
+        fn bounds(v: i32) -> [Bound<i32>; 3] {
+            [Bound::Included(v), Bound::Excluded(v), Bound::Unbounded]
+        }
+
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            let values: Vec<i32> = (0..200).map(|i| i * 3).collect();
+            for &v in &values {
+                btree.insert(v);
+            }
+
+            for lo in -2..602 {
+                for hi in [lo, lo + 1, lo + 3, lo + 20] {
+                    for range in bounds(lo)
+                        .into_iter()
+                        .flat_map(|s| bounds(hi).map(|e| (s, e)))
+                    {
+                        let expected: Vec<&i32> =
+                            values.iter().filter(|v| range.contains(v)).collect();
+                        assert_eq!(btree.first_in_range(range), expected.first().copied());
+                        assert_eq!(btree.last_in_range(range), expected.last().copied());
+                    }
+                }
+            }
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn zero_sized() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static DROPS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.with(|d| d.set(d.get() + 1));
+            }
+        }
+
+        let mut set = OkBTree::<()>::new();
+        assert_eq!(set.insert(()), None);
+        assert_eq!(set.insert(()), Some(()));
+        assert_eq!(set.get(&()), Some(&()));
+        assert_eq!(set.remove(&()), Some(()));
+        assert_eq!(set.remove(&()), None);
+
+        let mut bag = OkBTree::<Token, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for _ in 0..100 {
+            bag.insert(Token);
+        }
+        for _ in 0..40 {
+            assert!(bag.remove_first().is_some());
+        }
+        assert!(bag.first().is_some());
+        assert_eq!(DROPS.with(Cell::get), 40);
+        drop(bag);
+        assert_eq!(DROPS.with(Cell::get), 100);
+    }
+
+    #[test]
+    fn auto_traits_follow_t() {
+        use std::{cell::Cell, rc::Rc};
+
+        // `some_item` is ambiguous, and fails to compile, for any type that implements the
+        // trait in question.
+        trait AmbiguousIfSend<A> {
+            fn some_item() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+        impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+
+        trait AmbiguousIfSync<A> {
+            fn some_item() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+        impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+
+        <OkBTree<Rc<i32>> as AmbiguousIfSend<_>>::some_item();
+        <OkBTree<Cell<i32>> as AmbiguousIfSync<_>>::some_item();
+        <OkBTree<Rc<i32>> as AmbiguousIfSync<_>>::some_item();
+    }
 }
@@ -0,0 +1,67 @@
+/// Create an [`OkBTree`](crate::OkBTree) containing the given elements.
+///
+/// The elements are sorted and then appended in one pass, rather than being inserted one
+/// at a time. As with repeated calls to `insert`, the last of any equal elements is kept.
+///
+/// The fanout is taken from the surrounding context like the element type, so the result
+/// usually needs an annotation, such as `OkBTree<_>` for the default fanout.
+#[macro_export]
+macro_rules! okbtree {
+    () => {
+        $crate::OkBTree::default()
+    };
+    ($($value:expr),+ $(,)?) => {{
+        let mut values = ::std::vec![$($value),+];
+        // a stable sort keeps equal elements in order, so after reversing, the last of
+        // them comes first and is the one that dedup keeps.
+        values.sort();
+        values.reverse();
+        values.dedup();
+        $crate::OkBTree::from_sorted_unchecked(values.into_iter().rev())
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use crate::OkBTree;
+
+    #[test]
+    fn okbtree() {
+        let empty: OkBTree<i32> = okbtree![];
+        assert!(empty.first().is_none());
+
+        let btree: OkBTree<_> = okbtree![5, 3, 9, 1, 3, 7,];
+        assert_eq!(btree.first(), Some(&1));
+        assert_eq!(btree.last(), Some(&9));
+        assert_eq!(btree.get(&3), Some(&3));
+        assert_eq!(btree.get(&4), None);
+
+        let small: OkBTree<i32, 2> = okbtree![5, 3, 9, 1, 3, 7];
+        small.validate().unwrap();
+        assert!(small.iter().eq(&[1, 3, 5, 7, 9]));
+
+        // compares on the first field only
+        #[derive(Debug)]
+        struct ByKey(i32, &'static str);
+        impl PartialEq for ByKey {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for ByKey {}
+        impl PartialOrd for ByKey {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for ByKey {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let btree: OkBTree<_> = okbtree![ByKey(1, "a"), ByKey(1, "b"), ByKey(0, "c")];
+        assert_eq!(btree.first().unwrap().1, "c");
+        assert_eq!(btree.last().unwrap().1, "b");
+    }
+}
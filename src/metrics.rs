@@ -0,0 +1,221 @@
+//! Latency instrumentation, available with the `metrics` feature.
+
+use std::{ops::Deref, time::Duration};
+
+use equivalent::Comparable;
+
+use crate::{Clock, OkBTree, StdClock};
+
+/// A histogram of operation latencies, with power-of-two nanosecond buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// Bucket `i` counts latencies in `2^(i-1)..2^i` nanoseconds. Bucket 0 counts zeros.
+    buckets: [u64; 65],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: [0; 65],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[(u64::BITS - nanos.leading_zeros()) as usize] += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(latency);
+        self.max = self.max.max(latency);
+    }
+
+    /// The number of recorded operations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of all recorded latencies, saturating at `Duration::MAX`.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The largest recorded latency.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// An upper bound on the latency of the `q`th quantile of operations, with `q` in
+    /// `0.0..=1.0`. The bound is within a factor of two of the true value.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank.max(1) {
+                // the last bucket is unbounded, so the max is the best bound there is.
+                return 1u64
+                    .checked_shl(i as u32)
+                    .map_or(self.max, |b| Duration::from_nanos(b - 1).min(self.max));
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+/// Latency histograms for each instrumented operation, from [`Instrumented::metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub insert: LatencyHistogram,
+    pub get: LatencyHistogram,
+    pub remove: LatencyHistogram,
+}
+
+/// An [`OkBTree`] that records how long each `insert`, `get` and `remove` takes.
+///
+/// Other reads go straight to the underlying tree through `Deref` and are not recorded.
+/// Latencies are measured with the [`Clock`] `C`.
+pub struct Instrumented<T, const M: usize = 8, C = StdClock> {
+    tree: OkBTree<T, M>,
+    metrics: Metrics,
+    clock: C,
+}
+
+impl<T: Ord, const M: usize> Instrumented<T, M> {
+    pub fn new(tree: OkBTree<T, M>) -> Self {
+        Self::with_clock(tree, StdClock::default())
+    }
+}
+
+impl<T: Ord, const M: usize, C: Clock> Instrumented<T, M, C> {
+    pub fn with_clock(tree: OkBTree<T, M>, clock: C) -> Self {
+        Instrumented {
+            tree,
+            metrics: Metrics::default(),
+            clock,
+        }
+    }
+
+    /// The latencies recorded so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Clear the recorded latencies, returning them.
+    pub fn take_metrics(&mut self) -> Metrics {
+        std::mem::take(&mut self.metrics)
+    }
+
+    pub fn into_inner(self) -> OkBTree<T, M> {
+        self.tree
+    }
+
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let start = self.clock.now();
+        let res = self.tree.insert(value);
+        self.metrics
+            .insert
+            .record(self.clock.now().saturating_sub(start));
+        res
+    }
+
+    pub fn get<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<&T> {
+        let start = self.clock.now();
+        let res = self.tree.get(q);
+        self.metrics
+            .get
+            .record(self.clock.now().saturating_sub(start));
+        res
+    }
+
+    pub fn remove<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<T> {
+        let start = self.clock.now();
+        let res = self.tree.remove(q);
+        self.metrics
+            .remove
+            .record(self.clock.now().saturating_sub(start));
+        res
+    }
+}
+
+impl<T, const M: usize, C> Deref for Instrumented<T, M, C> {
+    type Target = OkBTree<T, M>;
+
+    fn deref(&self) -> &OkBTree<T, M> {
+        &self.tree
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Instrumented, LatencyHistogram};
+    use crate::{Clock, ManualClock, OkBTree};
+
+    #[test]
+    fn latency_histogram() {
+        let mut h = LatencyHistogram::default();
+        assert_eq!(h.quantile(0.5), Duration::ZERO);
+
+        for nanos in [0, 1, 100, 120, 1000, 5000] {
+            h.record(Duration::from_nanos(nanos));
+        }
+        assert_eq!(h.count(), 6);
+        assert_eq!(h.total(), Duration::from_nanos(6221));
+        assert_eq!(h.max(), Duration::from_nanos(5000));
+        assert_eq!(h.quantile(0.0), Duration::ZERO);
+        assert_eq!(h.quantile(0.5), Duration::from_nanos(127));
+        assert_eq!(h.quantile(0.8), Duration::from_nanos(1023));
+        assert_eq!(h.quantile(1.0), Duration::from_nanos(5000));
+
+        h.record(Duration::MAX);
+        h.record(Duration::MAX);
+        assert_eq!(h.total(), Duration::MAX);
+        assert_eq!(h.quantile(1.0), Duration::MAX);
+    }
+
+    #[test]
+    fn instrumented() {
+        let mut tree = Instrumented::new(OkBTree::new());
+        for i in 0..100 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.get(&5), Some(&5));
+        assert_eq!(tree.remove(&5), Some(5));
+        assert_eq!(tree.remove(&5), None);
+        assert_eq!(tree.first(), Some(&0));
+
+        let metrics = tree.take_metrics();
+        assert_eq!(metrics.insert.count(), 100);
+        assert_eq!(metrics.get.count(), 1);
+        assert_eq!(metrics.remove.count(), 2);
+        assert_eq!(tree.metrics().insert.count(), 0);
+    }
+
+    #[test]
+    fn deterministic_clock() {
+        // every reading moves the clock on by 10ns
+        struct Ticking(ManualClock);
+        impl Clock for Ticking {
+            fn now(&self) -> Duration {
+                self.0.advance(Duration::from_nanos(10));
+                self.0.now()
+            }
+        }
+
+        let mut tree = Instrumented::with_clock(OkBTree::new(), Ticking(ManualClock::default()));
+        for i in 0..10 {
+            tree.insert(i);
+        }
+        let metrics = tree.metrics();
+        assert_eq!(metrics.insert.total(), Duration::from_nanos(100));
+        assert_eq!(metrics.insert.max(), Duration::from_nanos(10));
+    }
+}
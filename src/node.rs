@@ -0,0 +1,1192 @@
+//! How [`OkBTree`](crate::OkBTree) searches its nodes.
+//!
+//! [`BinarySearch`] decides which pivot or child a descent takes, and [`Comp`], [`First`]
+//! and [`Last`] are the built-in policies. Custom policies can be passed to
+//! [`OkBTree::get_by`](crate::OkBTree::get_by) and
+//! [`OkBTree::remove_by`](crate::OkBTree::remove_by). [`NodeRef`] gives read-only access to
+//! the nodes themselves. The node storage is not exposed, and there are no hooks for
+//! augmenting nodes with extra data.
+//!
+//! This module is only public with the `unstable` feature, and may change in any release.
+
+use std::{
+    hint::unreachable_unchecked,
+    mem::{self, MaybeUninit},
+    ops::Bound,
+    ptr::{self, addr_of, addr_of_mut, NonNull},
+};
+
+use equivalent::Comparable;
+
+use crate::{
+    arrayvec::{self, DetachedArrayVec},
+    DuplicatePolicy,
+};
+
+impl<T, const M: usize> Children<T, M> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: MaybeUninit::uninit(),
+            tail: DetachedArrayVec::new(),
+        }
+    }
+}
+
+pub(crate) struct NodeArray<T, const M: usize> {
+    pub(crate) len: usize,
+    pub(crate) pivots: DetachedArrayVec<T, M>,
+    // empty if height = 0
+    pub(crate) children: Children<T, M>,
+}
+
+impl<T, const M: usize> NodeArray<T, M> {
+    /// A pointer to the pivot at `index`.
+    pub(crate) fn slot_ptr(&self, index: usize) -> NonNull<T> {
+        // SAFETY: `len` pivots are init
+        NonNull::from(&unsafe { self.pivots.as_slice(self.len) }[index])
+    }
+
+    /// # Safety
+    /// height must be correct.
+    pub(crate) unsafe fn drop_inner(&mut self, height: usize) {
+        if std::mem::needs_drop::<T>() {
+            // SAFETY: len pivots are init
+            unsafe { self.pivots.clear(self.len) };
+        }
+        if height > 0 {
+            debug_assert!(self.len > 0);
+            // SAFETY: internal nodes must always have children
+            unsafe { self.children.head.assume_init_read().drop_inner(height - 1) };
+
+            let tail = self.children.tail.take();
+
+            // SAFETY: len children are init in the tail.
+            for mut c in unsafe { tail.into_iter(self.len) } {
+                // SAFETY: height is correct and doesn't underflow.
+                unsafe { c.drop_inner(height - 1) };
+            }
+        }
+        self.len = 0;
+    }
+}
+
+pub(crate) struct Children<T, const M: usize> {
+    pub(crate) head: MaybeUninit<Box<NodeArray<T, M>>>,
+    pub(crate) tail: DetachedArrayVec<Box<NodeArray<T, M>>, M>,
+}
+
+impl<T, const M: usize> Children<T, M> {
+    pub(crate) fn get(&self, len: usize, index: usize) -> &NodeArray<T, M> {
+        match index.checked_sub(1) {
+            // SAFETY: head is always init when height > 0
+            None => unsafe { self.head.assume_init_ref() },
+            // SAFETY: tail len are init
+            Some(index) => unsafe { &self.tail.as_slice(len)[index] },
+        }
+    }
+    pub(crate) fn get_mut(&mut self, len: usize, index: usize) -> &mut NodeArray<T, M> {
+        match index.checked_sub(1) {
+            // SAFETY: head is always init when height > 0
+            None => unsafe { self.head.assume_init_mut() },
+            // SAFETY: tail len are init
+            Some(index) => unsafe { self.tail.as_mut_slice(len).get_unchecked_mut(index) },
+        }
+    }
+    pub(crate) fn get_ptr_mut(this: *mut Self, index: usize) -> *mut NodeArray<T, M> {
+        let boxed_node = match index.checked_sub(1) {
+            // SAFETY: head is always init when height > 0
+            None => unsafe { addr_of_mut!((*this).head).cast() },
+            Some(index) => unsafe {
+                DetachedArrayVec::get_ptr_mut(addr_of_mut!((*this).tail), index)
+            },
+        };
+        unsafe { addr_of_mut!(**boxed_node) }
+    }
+
+    pub(crate) unsafe fn push_front(&mut self, len: usize, t: Box<NodeArray<T, M>>) {
+        unsafe {
+            let head = mem::replace(self.head.assume_init_mut(), t);
+            self.tail.insert(len, 0, head);
+        }
+    }
+    pub(crate) unsafe fn pop_front(&mut self, len: usize) -> Box<NodeArray<T, M>> {
+        unsafe { mem::replace(self.head.assume_init_mut(), self.tail.remove(len, 0)) }
+    }
+}
+
+impl<T, const M: usize> NodeArray<T, M> {
+    /// The smallest element within the lower `bound`.
+    pub(crate) fn lower_bound<Q: Comparable<T> + ?Sized>(
+        &self,
+        height: usize,
+        bound: Bound<&Q>,
+    ) -> Option<&T> {
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        // the first pivot within the bound. Everything in the children after it is larger.
+        let index = pivots.partition_point(|p| match bound {
+            Bound::Included(q) => q.compare(p).is_gt(),
+            Bound::Excluded(q) => q.compare(p).is_ge(),
+            Bound::Unbounded => false,
+        });
+        if let (Bound::Included(q), Some(p)) = (bound, pivots.get(index)) {
+            if q.compare(p).is_eq() {
+                return Some(p);
+            }
+        }
+
+        if height > 0 {
+            let child = self.children.get(self.len, index);
+            if let Some(value) = child.lower_bound(height - 1, bound) {
+                return Some(value);
+            }
+        }
+        pivots.get(index)
+    }
+
+    /// The largest element within the upper `bound`.
+    pub(crate) fn upper_bound<Q: Comparable<T> + ?Sized>(
+        &self,
+        height: usize,
+        bound: Bound<&Q>,
+    ) -> Option<&T> {
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        // one past the last pivot within the bound. Everything in the children before it
+        // is smaller.
+        let index = pivots.partition_point(|p| match bound {
+            Bound::Included(q) => q.compare(p).is_ge(),
+            Bound::Excluded(q) => q.compare(p).is_gt(),
+            Bound::Unbounded => true,
+        });
+        if let (Bound::Included(q), Some(p)) = (bound, index.checked_sub(1).map(|i| &pivots[i])) {
+            if q.compare(p).is_eq() {
+                return Some(p);
+            }
+        }
+
+        if height > 0 {
+            let child = self.children.get(self.len, index);
+            if let Some(value) = child.upper_bound(height - 1, bound) {
+                return Some(value);
+            }
+        }
+        index.checked_sub(1).map(|index| &pivots[index])
+    }
+
+    /// The element equal to `q`, along with the number of elements before it.
+    pub(crate) fn get_full<Q: Comparable<T> + ?Sized>(
+        &self,
+        height: usize,
+        q: &Q,
+    ) -> Option<(usize, &T)> {
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        let (index, found) = match Comp::from_comp(q).binary_search(pivots, height) {
+            Ok(index) => (index, Some(&pivots[index])),
+            Err(index) => (index, None),
+        };
+
+        // everything in the children left of the one we descend into is smaller.
+        let mut rank = index;
+        if height > 0 {
+            let child = |index| self.children.get(self.len, index);
+            rank += (0..index)
+                .map(|i| child(i).count(height - 1))
+                .sum::<usize>();
+            if found.is_none() {
+                let (r, value) = child(index).get_full(height - 1, q)?;
+                return Some((rank + r, value));
+            }
+            rank += child(index).count(height - 1);
+        }
+        found.map(|value| (rank, value))
+    }
+
+    /// Visit all elements in order.
+    pub(crate) fn for_each<'a>(&'a self, height: usize, f: &mut impl FnMut(&'a T)) {
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        if height == 0 {
+            pivots.iter().for_each(f);
+        } else {
+            let child = |index| self.children.get(self.len, index);
+            child(0).for_each(height - 1, f);
+            for (index, pivot) in (1..).zip(pivots) {
+                f(pivot);
+                child(index).for_each(height - 1, f);
+            }
+        }
+    }
+
+    /// The number of elements in this subtree.
+    pub(crate) fn count(&self, height: usize) -> usize {
+        let mut count = self.len;
+        if height > 0 {
+            for index in 0..=self.len {
+                count += self.children.get(self.len, index).count(height - 1);
+            }
+        }
+        count
+    }
+
+    /// Visit all elements in `lo..=hi` in order, skipping any subtrees outside of that range.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn for_each_between<'a, Q1, Q2>(
+        &'a self,
+        height: usize,
+        lo: &Q1,
+        hi: &Q2,
+        f: &mut impl FnMut(&'a T),
+    ) where
+        Q1: Comparable<T> + ?Sized,
+        Q2: Comparable<T> + ?Sized,
+    {
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        let start = pivots.partition_point(|p| lo.compare(p).is_gt());
+        let end = pivots.partition_point(|p| hi.compare(p).is_ge());
+        if start > end {
+            return;
+        }
+
+        if height == 0 {
+            pivots[start..end].iter().for_each(f);
+        } else {
+            let child = |index| self.children.get(self.len, index);
+            child(start).for_each_between(height - 1, lo, hi, f);
+            for (index, pivot) in (start + 1..).zip(&pivots[start..end]) {
+                f(pivot);
+                child(index).for_each_between(height - 1, lo, hi, f);
+            }
+        }
+    }
+}
+
+impl<T: Ord, const M: usize> NodeArray<T, M> {
+    pub(crate) const __M_IS_GREATER_THAN_ONE: bool = {
+        assert!(M > 1, "The fanout factor, M, must be greater than one");
+        true
+    };
+    pub(crate) const __M_IS_EVEN: bool = {
+        assert!(M % 2 == 0, "The fanout factor, M, must be even");
+        true
+    };
+
+    #[cold]
+    pub(crate) fn insert_split(
+        &mut self,
+        index: usize,
+        value: T,
+        child: Option<Box<NodeArray<T, M>>>,
+    ) -> InsertResult<T, M> {
+        debug_assert_eq!(self.len, M);
+        debug_assert!(M >= 2);
+
+        let m2 = M / 2;
+        let m2m1 = m2 - 1;
+        let m2p1 = m2 + 1;
+        assert!(m2p1 <= M);
+        assert!(m2 > 0);
+
+        // we are creating a new node,
+        // the values being split off from rhs will be written here.
+        let mut new_node = NodeArray {
+            len: 0,
+            pivots: DetachedArrayVec::new(),
+            children: Children::<T, M>::new(),
+        };
+
+        let (mid, slot) = match usize::cmp(&index, &m2) {
+            std::cmp::Ordering::Equal => unsafe {
+                // SAFETY: M pivots are init. m2 < M.
+                new_node.pivots = self.pivots.split_off(M, m2);
+
+                if let Some(child) = child {
+                    new_node.children.head.write(child);
+                    // SAFETY: M children are init. m2 < M.
+                    new_node.children.tail = self.children.tail.split_off(M, m2);
+                }
+
+                (value, Slot::Pivot)
+            },
+            std::cmp::Ordering::Less => unsafe {
+                new_node.pivots = self.pivots.split_off(M, m2);
+                let mid = self.pivots.pop(m2);
+                self.pivots.insert(m2m1, index, value);
+
+                if let Some(child) = child {
+                    new_node.children.tail = self.children.tail.split_off(M, m2);
+                    new_node.children.head.write(self.children.tail.pop(m2));
+                    self.children.tail.insert(m2m1, index, child);
+                }
+
+                (mid, Slot::Here(index))
+            },
+            std::cmp::Ordering::Greater => unsafe {
+                let index = index - m2p1;
+                new_node.pivots = self.pivots.split_off(M, m2p1);
+                let mid = self.pivots.pop(m2p1);
+                new_node.pivots.insert(m2m1, index, value);
+
+                if let Some(child) = child {
+                    new_node.children.tail = self.children.tail.split_off(M, m2p1);
+                    new_node.children.head.write(self.children.tail.pop(m2p1));
+                    new_node.children.tail.insert(m2m1, index, child);
+                }
+
+                (mid, Slot::Right(index))
+            },
+        };
+        self.len = m2;
+        new_node.len = m2;
+        InsertResult::Propagate {
+            pivot: mid,
+            right: Box::new(new_node),
+            slot,
+        }
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        value: T,
+        height: usize,
+        mode: InsertMode,
+    ) -> InsertResult<T, M> {
+        assert!(Self::__M_IS_GREATER_THAN_ONE);
+        assert!(Self::__M_IS_EVEN);
+
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
+
+        let index = match mode {
+            InsertMode::Search(DuplicatePolicy::AllowDuplicates) => {
+                pivots.partition_point(|pivot| *pivot <= value)
+            }
+            InsertMode::Search(policy) => {
+                match Comp::from_comp(&value).binary_search(pivots, height) {
+                    Ok(index) => {
+                        // SAFETY: binary_search returns an index in bounds
+                        let existing = unsafe { pivots.get_unchecked_mut(index) };
+                        return match policy {
+                            DuplicatePolicy::KeepExisting | DuplicatePolicy::Reject => {
+                                InsertResult::Kept(Some(value), Slot::Here(index))
+                            }
+                            _ => InsertResult::Replaced(
+                                mem::replace(existing, value),
+                                Slot::Here(index),
+                            ),
+                        };
+                    }
+                    Err(index) => index,
+                }
+            }
+            InsertMode::Unique => pivots.partition_point(|pivot| *pivot < value),
+            InsertMode::Append => pivots.len(),
+        };
+
+        if height == 0 {
+            return self.insert_at(index, value, None);
+        }
+        debug_assert!(self.len > 0, "non leaf nodes must have some children");
+        let result = self
+            .children
+            .get_mut(self.len, index)
+            .insert(value, height - 1, mode);
+        self.ascend(index, result)
+    }
+
+    /// Find the element equal to `q`, or insert the one built by `f` where it belongs.
+    pub(crate) fn get_or_insert_with<Q, F>(
+        &mut self,
+        height: usize,
+        q: &Q,
+        f: F,
+    ) -> InsertResult<T, M>
+    where
+        Q: Comparable<T> + ?Sized,
+        F: FnOnce() -> T,
+    {
+        assert!(Self::__M_IS_GREATER_THAN_ONE);
+        assert!(Self::__M_IS_EVEN);
+
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_slice(self.len) };
+
+        let index = match Comp::from_comp(q).binary_search(pivots, height) {
+            Ok(index) => return InsertResult::Kept(None, Slot::Here(index)),
+            Err(index) => index,
+        };
+
+        if height == 0 {
+            let value = f();
+            debug_assert!(
+                q.compare(&value).is_eq(),
+                "insert_with called with a value not equal to the probe"
+            );
+            return self.insert_at(index, value, None);
+        }
+        debug_assert!(self.len > 0, "non leaf nodes must have some children");
+        let result = self
+            .children
+            .get_mut(self.len, index)
+            .get_or_insert_with(height - 1, q, f);
+        self.ascend(index, result)
+    }
+
+    /// Insert `value` and the child after it at `index`, splitting if this node is full.
+    fn insert_at(
+        &mut self,
+        index: usize,
+        value: T,
+        child: Option<Box<NodeArray<T, M>>>,
+    ) -> InsertResult<T, M> {
+        if self.len == M {
+            return self.insert_split(index, value, child);
+        }
+        // SAFETY:
+        // * len children and pivots are currently init
+        // * len is less than cap.
+        unsafe {
+            self.pivots.insert(self.len, index, value);
+            if let Some(child) = child {
+                self.children.tail.insert(self.len, index, child);
+            }
+            self.len += 1;
+        }
+        InsertResult::Done(Slot::Here(index))
+    }
+
+    /// Finish an insert into the child at `index`: add any node split off from it, and
+    /// track where the element ended up relative to this node.
+    fn ascend(&mut self, index: usize, result: InsertResult<T, M>) -> InsertResult<T, M> {
+        let (pivot, right, slot) = match result {
+            InsertResult::Propagate { pivot, right, slot } => (pivot, right, slot),
+            InsertResult::Done(slot) => return InsertResult::Done(self.below(index, slot)),
+            InsertResult::Kept(value, slot) => {
+                return InsertResult::Kept(value, self.below(index, slot))
+            }
+            InsertResult::Replaced(value, slot) => {
+                return InsertResult::Replaced(value, self.below(index, slot))
+            }
+        };
+
+        let mut result = self.insert_at(index, pivot, Some(right));
+        // a split can move the child and the node split off from it by value, so slots in
+        // them are only resolved once they are stored for good. They are now children
+        // `index` and `index + 1`, of either half if this node split.
+        let (child, i) = match slot {
+            // the propagated pivot was the element, and insert_at tracked it.
+            Slot::Pivot => return result,
+            Slot::Below(_) => {
+                result.set_slot(slot);
+                return result;
+            }
+            Slot::Here(i) => (index, i),
+            Slot::Right(i) => (index + 1, i),
+        };
+        let node = match &result {
+            InsertResult::Propagate { right, .. } if child > M / 2 => {
+                right.children.get(right.len, child - M / 2 - 1)
+            }
+            _ => self.children.get(self.len, child),
+        };
+        let ptr = node.slot_ptr(i);
+        result.set_slot(Slot::Below(ptr));
+        result
+    }
+
+    /// Resolve a slot in the child at `index` to a pointer.
+    fn below(&self, index: usize, slot: Slot<T>) -> Slot<T> {
+        match slot {
+            Slot::Here(i) => Slot::Below(self.children.get(self.len, index).slot_ptr(i)),
+            slot => slot,
+        }
+    }
+
+    pub(crate) unsafe fn search_raw<B: BinarySearch<T> + ?Sized>(
+        this: *mut Self,
+        height: usize,
+        b: &B,
+    ) -> Option<(usize, *mut NodeArray<T, M>)> {
+        assert!(Self::__M_IS_GREATER_THAN_ONE);
+        assert!(Self::__M_IS_EVEN);
+
+        // SAFETY: caller must assert that this is readable.
+        let len = unsafe { *addr_of!((*this).len) };
+        let pivots = unsafe { &*addr_of!((*this).pivots) };
+
+        let index = {
+            // temporarily borrow pivots to perform the binary search.
+            // SAFETY: `len` pivots are init
+            let pivots = unsafe { pivots.as_slice(len) };
+
+            match b.binary_search(pivots, height) {
+                Ok(index) => return Some((index, this)),
+                Err(index) => index,
+            }
+        };
+
+        if height == 0 {
+            return None;
+        }
+
+        debug_assert!(len > 0, "non leaf nodes must have some children");
+        // SAFETY: caller must assert that this is readable.
+        // for height > 0, children are always init.
+        let children = unsafe { addr_of_mut!((*this).children) };
+        let child = Children::get_ptr_mut(children, index);
+
+        unsafe { Self::search_raw(child, height - 1, b) }
+    }
+
+    // ok - no underflow
+    // err - underflow
+    pub(crate) fn remove<B: BinarySearch<T> + ?Sized>(
+        &mut self,
+        height: usize,
+        b: &B,
+    ) -> Option<RemoveResult<T>> {
+        assert!(Self::__M_IS_GREATER_THAN_ONE);
+        assert!(Self::__M_IS_EVEN);
+
+        // SAFETY: `len` pivots are init
+        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
+
+        let binary_search = b.binary_search(pivots, height);
+
+        // leaf node.
+        if height == 0 {
+            let index = b.binary_search(pivots, height).ok()?;
+
+            let value = unsafe { self.pivots.remove(self.len, index) };
+            self.len -= 1;
+
+            if self.len < M / 2 {
+                return Some(RemoveResult::Underflow(value));
+            } else {
+                return Some(RemoveResult::Done(value));
+            }
+        }
+
+        let (Ok(index) | Err(index)) = binary_search;
+        let child = self.children.get_mut(self.len, index);
+        let value = match binary_search {
+            Ok(_) => child
+                .remove(height - 1, &Last)?
+                .map(|v| std::mem::replace(unsafe { pivots.get_unchecked_mut(index) }, v)),
+            Err(_) => child.remove(height - 1, b)?,
+        };
+        let value = match value {
+            RemoveResult::Done(value) => return Some(RemoveResult::Done(value)),
+            RemoveResult::Underflow(value) => value,
+        };
+
+        let index = match index.checked_sub(1) {
+            // SAFETY: head is always init when height > 0
+            None => unsafe {
+                let child = self.children.head.assume_init_mut();
+                let next_child = self
+                    .children
+                    .tail
+                    .as_mut_slice(self.len)
+                    .get_unchecked_mut(0);
+                let pivot = pivots.get_unchecked_mut(0);
+
+                if next_child.len > M / 2 {
+                    Self::rotate_left(height, child, pivot, next_child);
+                    return Some(RemoveResult::Done(value));
+                }
+
+                // we can only merge
+                let child = std::mem::replace(child, self.children.tail.remove(self.len, 0));
+                let pivot = self.pivots.remove(self.len, 0);
+                self.len -= 1;
+
+                let next_child = self.children.head.assume_init_mut();
+
+                Self::merge_left(height, *child, pivot, next_child);
+
+                if self.len < M / 2 {
+                    return Some(RemoveResult::Underflow(value));
+                } else {
+                    return Some(RemoveResult::Done(value));
+                }
+            },
+            Some(index) => index,
+        };
+
+        let pivots = unsafe { self.pivots.as_mut_slice(self.len) };
+        // debug_assert_eq!(child.len, M / 2 - 1);
+
+        // check the right sibling first
+        if index + 1 < self.len {
+            unsafe {
+                let children = self.children.tail.as_mut_slice(self.len);
+                let [child, next_child] = children.get_unchecked_mut(index..index + 2) else {
+                    unreachable_unchecked()
+                };
+                let pivot = &mut pivots[index + 1];
+
+                if next_child.len > M / 2 {
+                    Self::rotate_left(height, child, pivot, next_child);
+                    return Some(RemoveResult::Done(value));
+                }
+            }
+        }
+
+        let [prev_child, child] = {
+            match index.checked_sub(1) {
+                None => unsafe {
+                    [
+                        self.children.head.assume_init_mut(),
+                        self.children
+                            .tail
+                            .as_mut_slice(self.len)
+                            .get_unchecked_mut(0),
+                    ]
+                },
+                Some(index) => unsafe {
+                    let children = self.children.tail.as_mut_slice(self.len);
+                    let x: &mut [_; 2] = (children.get_unchecked_mut(index..index + 2))
+                        .try_into()
+                        .unwrap_unchecked();
+                    x.each_mut()
+                },
+            }
+        };
+        let pivot = &mut pivots[index];
+
+        if prev_child.len > M / 2 {
+            Self::rotate_right(height, prev_child, pivot, child);
+            return Some(RemoveResult::Done(value));
+        }
+
+        // we can only merge
+
+        let child = unsafe { self.children.tail.remove(self.len, index) };
+        let pivot = unsafe { self.pivots.remove(self.len, index) };
+        self.len -= 1;
+
+        let prev_child = self.children.get_mut(self.len, index);
+        Self::merge_right(height, prev_child, pivot, *child);
+
+        if self.len < M / 2 {
+            Some(RemoveResult::Underflow(value))
+        } else {
+            Some(RemoveResult::Done(value))
+        }
+    }
+
+    pub(crate) fn merge_right(
+        height: usize,
+        lhs: &mut NodeArray<T, M>,
+        pivot: T,
+        rhs: NodeArray<T, M>,
+    ) {
+        debug_assert_eq!(lhs.len + rhs.len + 1, M);
+        unsafe {
+            lhs.pivots.push(M / 2, pivot);
+            lhs.pivots.append(M / 2 + 1, rhs.pivots, M / 2 - 1);
+            if height > 1 {
+                lhs.children
+                    .tail
+                    .push(M / 2, rhs.children.head.assume_init_read());
+                lhs.children
+                    .tail
+                    .append(M / 2 + 1, rhs.children.tail, M / 2 - 1);
+            }
+            lhs.len = M;
+        }
+    }
+
+    pub(crate) fn merge_left(
+        height: usize,
+        lhs: NodeArray<T, M>,
+        pivot: T,
+        rhs: &mut NodeArray<T, M>,
+    ) {
+        debug_assert_eq!(lhs.len + rhs.len + 1, M);
+        let x = std::mem::replace(rhs, lhs);
+        let (lhs, rhs) = (rhs, x);
+
+        unsafe {
+            lhs.pivots.push(M / 2 - 1, pivot);
+            lhs.pivots.append(M / 2, rhs.pivots, M / 2);
+            if height > 1 {
+                lhs.children
+                    .tail
+                    .push(M / 2 - 1, rhs.children.head.assume_init_read());
+                lhs.children.tail.append(M / 2, rhs.children.tail, M / 2);
+            }
+            lhs.len = M;
+        }
+    }
+
+    pub(crate) fn rotate_right(
+        height: usize,
+        lhs: &mut NodeArray<T, M>,
+        pivot: &mut T,
+        rhs: &mut NodeArray<T, M>,
+    ) {
+        debug_assert!(height > 0);
+        debug_assert!(lhs.len > M / 2);
+        debug_assert_eq!(rhs.len, M / 2 - 1);
+
+        if height == 1 {
+            // lhs and rhs are leaf nodes
+            unsafe {
+                let new = lhs.pivots.pop(lhs.len);
+                lhs.len -= 1;
+
+                let old = std::mem::replace(pivot, new);
+                rhs.pivots.insert(M / 2 - 1, 0, old);
+
+                rhs.len += 1;
+            }
+        } else {
+            // lhs and rhs are internal nodes
+            unsafe {
+                let child = lhs.children.tail.pop(lhs.len);
+                let new = lhs.pivots.pop(lhs.len);
+                lhs.len -= 1;
+
+                let old = std::mem::replace(pivot, new);
+                rhs.pivots.insert(M / 2 - 1, 0, old);
+                rhs.children.push_front(M / 2 - 1, child);
+
+                rhs.len += 1;
+            }
+        }
+    }
+
+    pub(crate) fn rotate_left(
+        height: usize,
+        lhs: &mut NodeArray<T, M>,
+        pivot: &mut T,
+        rhs: &mut NodeArray<T, M>,
+    ) {
+        debug_assert!(height > 0);
+        debug_assert!(rhs.len > M / 2);
+        debug_assert_eq!(lhs.len, M / 2 - 1);
+
+        if height == 1 {
+            // lhs and rhs are leaf nodes
+            unsafe {
+                let new = rhs.pivots.remove(rhs.len, 0);
+                rhs.len -= 1;
+
+                let old = std::mem::replace(pivot, new);
+                lhs.pivots.push(M / 2 - 1, old);
+                lhs.len += 1;
+            }
+        } else {
+            // lhs and rhs are internal nodes
+            unsafe {
+                let child = rhs.children.pop_front(rhs.len);
+                let new = rhs.pivots.remove(rhs.len, 0);
+                rhs.len -= 1;
+
+                let old = std::mem::replace(pivot, new);
+                lhs.pivots.push(M / 2 - 1, old);
+                lhs.children.tail.push(M / 2 - 1, child);
+                lhs.len += 1;
+            }
+        }
+    }
+}
+
+/// A policy for descending the tree, one node at a time.
+///
+/// # Safety
+/// The tree indexes into its nodes with the result without checking it. An `Ok` index must
+/// be less than `pivots.len()`, and an `Err` index must be at most `pivots.len()`.
+pub unsafe trait BinarySearch<K> {
+    /// Search the pivots of a node at the given height.
+    ///
+    /// Return `Ok(i)` to stop at the pivot `i`, or `Err(i)` to continue into child `i`.
+    /// `Err` from a leaf, at height 0, means the search found nothing.
+    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize>;
+}
+
+// SAFETY: slice::binary_search_by returns indices within these bounds.
+unsafe impl<K, Q: Comparable<K> + ?Sized> BinarySearch<K> for Comp<Q> {
+    fn binary_search(&self, pivots: &[K], _height: usize) -> Result<usize, usize> {
+        pivots.binary_search_by(|pivot| self.0.compare(pivot).reverse())
+    }
+}
+
+/// Searches for the element equal to the wrapped probe.
+#[repr(transparent)]
+pub struct Comp<Q: ?Sized>(pub Q);
+
+impl<Q: ?Sized> Comp<Q> {
+    pub fn from_comp(q: &Q) -> &Self {
+        // SAFETY: transparent wrapper, so the pointer metadata is the same
+        unsafe { &*(q as *const Q as *const Self) }
+    }
+}
+
+/// Searches for one specific element by address, steering by a probe equal to its old
+/// value. This finds an element that was modified in place and may now be out of order,
+/// as long as no other element is equal to the probe.
+pub(crate) struct At<'a, Q: ?Sized, T> {
+    pub(crate) q: &'a Q,
+    pub(crate) target: *const T,
+}
+
+// SAFETY: slice::binary_search_by returns indices within these bounds.
+unsafe impl<T, Q: Comparable<T> + ?Sized> BinarySearch<T> for At<'_, Q, T> {
+    fn binary_search(&self, pivots: &[T], _height: usize) -> Result<usize, usize> {
+        pivots.binary_search_by(|pivot| {
+            if ptr::eq(pivot, self.target) {
+                std::cmp::Ordering::Equal
+            } else {
+                self.q.compare(pivot).reverse()
+            }
+        })
+    }
+}
+
+/// Searches for one specific element by address, which must be the first element equal to
+/// the probe. Unlike [`At`], this finds it among any number of equal elements.
+pub(crate) struct FirstAt<'a, Q: ?Sized, T> {
+    pub(crate) q: &'a Q,
+    pub(crate) target: *const T,
+}
+
+// SAFETY: partition_point returns at most pivots.len(), and Ok is only returned for an
+// index it has checked is less than that.
+unsafe impl<T, Q: Comparable<T> + ?Sized> BinarySearch<T> for FirstAt<'_, Q, T> {
+    fn binary_search(&self, pivots: &[T], _height: usize) -> Result<usize, usize> {
+        // the first pivot not less than the probe. If it is not the target, then the target
+        // comes before it, in the child to its left.
+        let index = pivots.partition_point(|pivot| self.q.compare(pivot).is_gt());
+        match pivots.get(index) {
+            Some(pivot) if ptr::eq(pivot, self.target) => Ok(index),
+            _ => Err(index),
+        }
+    }
+}
+
+/// Searches for the largest element.
+pub struct Last;
+
+// SAFETY: Ok is only returned for a non-empty node.
+unsafe impl<K> BinarySearch<K> for Last {
+    #[inline]
+    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize> {
+        if height == 0 && !pivots.is_empty() {
+            Ok(pivots.len() - 1)
+        } else {
+            Err(pivots.len())
+        }
+    }
+}
+
+/// Searches for the smallest element.
+pub struct First;
+
+// SAFETY: Ok is only returned for a non-empty node.
+unsafe impl<K> BinarySearch<K> for First {
+    #[inline]
+    fn binary_search(&self, pivots: &[K], height: usize) -> Result<usize, usize> {
+        if height == 0 && !pivots.is_empty() {
+            Ok(0)
+        } else {
+            Err(0)
+        }
+    }
+}
+
+/// A read-only view of a single node in an [`OkBTree`](crate::OkBTree), as passed to
+/// [`OkBTree::visit_nodes`](crate::OkBTree::visit_nodes).
+pub struct NodeRef<'a, T, const M: usize> {
+    pub(crate) height: usize,
+    pub(crate) node: &'a NodeArray<T, M>,
+}
+
+impl<T, const M: usize> Clone for NodeRef<'_, T, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const M: usize> Copy for NodeRef<'_, T, M> {}
+
+impl<'a, T, const M: usize> NodeRef<'a, T, M> {
+    /// The distance from this node to the leaves. Leaves have height 0.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The elements stored in this node, in ascending order.
+    ///
+    /// For an internal node, the pivot at `i` sits between the elements of children `i` and `i + 1`.
+    pub fn pivots(&self) -> &'a [T] {
+        // SAFETY: len pivots are init
+        unsafe { self.node.pivots.as_slice(self.node.len) }
+    }
+
+    /// The number of children of this node: zero for a leaf, otherwise one more than the
+    /// number of pivots.
+    pub fn child_count(&self) -> usize {
+        if self.height == 0 {
+            0
+        } else {
+            self.node.len + 1
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.height == 0
+    }
+
+    /// The child at `index`. The node must not be a leaf.
+    pub(crate) fn child(self, index: usize) -> NodeRef<'a, T, M> {
+        NodeRef {
+            height: self.height - 1,
+            node: self.node.children.get(self.node.len, index),
+        }
+    }
+
+    pub(crate) fn visit(self, f: &mut impl FnMut(NodeRef<'a, T, M>)) {
+        f(self);
+        for index in 0..self.child_count() {
+            self.child(index).visit(f);
+        }
+    }
+}
+
+/// A subtree that owns its elements, dropping them along with its nodes.
+pub(crate) struct OwnedNode<T, const M: usize> {
+    pub(crate) height: usize,
+    pub(crate) node: Box<NodeArray<T, M>>,
+}
+
+impl<T, const M: usize> OwnedNode<T, M> {
+    /// Free the root node of this subtree, returning its first child, its pivots and its
+    /// remaining children. Leaves have no children.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn split(
+        self,
+    ) -> (
+        Option<OwnedNode<T, M>>,
+        arrayvec::IntoIter<T, M>,
+        arrayvec::IntoIter<Box<NodeArray<T, M>>, M>,
+    ) {
+        let this = mem::ManuallyDrop::new(self);
+        let height = this.height;
+        // SAFETY: `this` is never used again, so the box is moved out exactly once.
+        let mut node = unsafe { ptr::read(&this.node) };
+        let len = mem::replace(&mut node.len, 0);
+
+        // SAFETY: len pivots are init, and for an internal node so are the head and len
+        // children of the tail. They are all moved out before the node is freed.
+        unsafe {
+            let pivots = node.pivots.take().into_iter(len);
+            if height == 0 {
+                return (None, pivots, DetachedArrayVec::new().into_iter(0));
+            }
+            let head = OwnedNode {
+                height: height - 1,
+                node: node.children.head.assume_init_read(),
+            };
+            let tail = node.children.tail.take().into_iter(len);
+            (Some(head), pivots, tail)
+        }
+    }
+}
+
+impl<T: Clone, const M: usize> OwnedNode<T, M> {
+    /// Copy the subtree at `node`, keeping its shape. If cloning an element panics, the
+    /// parts copied so far are dropped.
+    pub(crate) fn clone_from_ref(node: NodeRef<'_, T, M>) -> Self {
+        let pivots = node.pivots().to_vec();
+        let children: Vec<Self> = (0..node.child_count())
+            .map(|index| Self::clone_from_ref(node.child(index)))
+            .collect();
+
+        let mut array = Box::new(NodeArray {
+            len: pivots.len(),
+            pivots: DetachedArrayVec::new(),
+            children: Children::new(),
+        });
+
+        // SAFETY:
+        // pivots and children are currently uninit.
+        // the source node held len <= M pivots and len + 1 children, if any.
+        unsafe {
+            for (i, pivot) in pivots.into_iter().enumerate() {
+                array.pivots.push(i, pivot);
+            }
+            let mut children = children.into_iter().map(OwnedNode::into_inner);
+            if let Some(head) = children.next() {
+                array.children.head.write(head);
+                for (i, child) in children.enumerate() {
+                    array.children.tail.push(i, child);
+                }
+            }
+        }
+
+        OwnedNode {
+            height: node.height,
+            node: array,
+        }
+    }
+}
+
+impl<T, const M: usize> OwnedNode<T, M> {
+    /// Take the root node of this subtree without dropping its contents.
+    pub(crate) fn into_inner(self) -> Box<NodeArray<T, M>> {
+        let this = mem::ManuallyDrop::new(self);
+        // SAFETY: `this` is never used again, so the box is moved out exactly once.
+        unsafe { ptr::read(&this.node) }
+    }
+}
+
+impl<T, const M: usize> Drop for OwnedNode<T, M> {
+    fn drop(&mut self) {
+        // SAFETY: height is set correctly.
+        unsafe { self.node.drop_inner(self.height) }
+    }
+}
+
+pub(crate) struct NodeArrayFmt<'a, T, const M: usize> {
+    pub(crate) height: usize,
+    pub(crate) array: &'a NodeArray<T, M>,
+}
+
+impl<T: std::fmt::Debug, const M: usize> std::fmt::Debug for NodeArrayFmt<'_, T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut list = f.debug_list();
+
+        // SAFETY: len pivots are init
+        let pivots = unsafe { self.array.pivots.as_slice(self.array.len) };
+
+        if self.height == 0 {
+            list.entries(pivots);
+        } else {
+            debug_assert_ne!(self.array.len, 0);
+
+            list.entry(&NodeArrayFmt {
+                height: self.height - 1,
+                // SAFETY: head is always init when height > 0
+                array: unsafe { self.array.children.head.assume_init_ref() },
+            });
+
+            // SAFETY: len children are init
+            let tail = unsafe { self.array.children.tail.as_slice(self.array.len) };
+            for (p, c) in std::iter::zip(pivots, tail) {
+                list.entry(p);
+                list.entry(&NodeArrayFmt {
+                    height: self.height - 1,
+                    array: c,
+                });
+            }
+        }
+        list.finish()
+    }
+}
+
+/// How [`NodeArray::insert`] finds where the new value goes.
+#[derive(Clone, Copy)]
+pub(crate) enum InsertMode {
+    /// Search for the value, handling any equal element according to the policy.
+    Search(DuplicatePolicy),
+    /// Search for the value, which the caller guarantees is not already present.
+    Unique,
+    /// Insert at the end. The caller guarantees the value is greater than all elements.
+    Append,
+}
+
+pub(crate) enum InsertResult<T, const M: usize> {
+    Propagate {
+        pivot: T,
+        right: Box<NodeArray<T, M>>,
+        slot: Slot<T>,
+    },
+    /// The new element was inserted at `slot`.
+    Done(Slot<T>),
+    /// An equal element was found at `slot` and kept. The new one is handed back, if it
+    /// was built.
+    Kept(Option<T>, Slot<T>),
+    /// An equal element was found and replaced by the new one at `slot`. This is the old one.
+    Replaced(T, Slot<T>),
+}
+
+impl<T, const M: usize> InsertResult<T, M> {
+    fn set_slot(&mut self, new: Slot<T>) {
+        match self {
+            InsertResult::Propagate { slot, .. }
+            | InsertResult::Done(slot)
+            | InsertResult::Kept(_, slot)
+            | InsertResult::Replaced(_, slot) => *slot = new,
+        }
+    }
+}
+
+/// Where the element equal to the inserted one is, relative to the node that returned the
+/// [`InsertResult`].
+///
+/// Pointers are only taken once the node holding the element will not be moved by value
+/// again, which would invalidate them. A node is only moved by value while its parent is
+/// being changed, so a pointer into a child is taken after its parent is done.
+pub(crate) enum Slot<T> {
+    /// At this index in the node's own pivots.
+    Here(usize),
+    /// At this index in the pivots of the new right node from [`InsertResult::Propagate`].
+    Right(usize),
+    /// It is the pivot from [`InsertResult::Propagate`].
+    Pivot,
+    /// In a node further down.
+    Below(NonNull<T>),
+}
+
+pub(crate) enum RemoveResult<T> {
+    Underflow(T),
+    Done(T),
+}
+
+impl<T> RemoveResult<T> {
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> RemoveResult<U> {
+        match self {
+            RemoveResult::Underflow(value) => RemoveResult::Underflow(f(value)),
+            RemoveResult::Done(value) => RemoveResult::Done(f(value)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "unstable"))]
+mod test {
+    use super::{BinarySearch, First};
+    use crate::OkBTree;
+
+    /// Searches `(key, value)` pairs by key alone.
+    struct ByKey(u32);
+
+    // SAFETY: slice::binary_search_by returns indices within the bounds.
+    unsafe impl<V> BinarySearch<(u32, V)> for ByKey {
+        fn binary_search(&self, pivots: &[(u32, V)], _height: usize) -> Result<usize, usize> {
+            pivots.binary_search_by_key(&self.0, |(key, _)| *key)
+        }
+    }
+
+    #[test]
+    fn custom_policy() {
+        let mut btree = OkBTree::<_, 4>::default();
+        for i in 0..100 {
+            btree.insert((i, i.to_string()));
+        }
+
+        assert_eq!(btree.get_by(&ByKey(42)), Some(&(42, "42".to_owned())));
+        assert_eq!(btree.remove_by(&ByKey(42)), Some((42, "42".to_owned())));
+        assert_eq!(btree.get_by(&ByKey(42)), None);
+        assert_eq!(btree.remove_by(&First), Some((0, "0".to_owned())));
+    }
+}
@@ -0,0 +1,442 @@
+use std::{fmt, mem::ManuallyDrop, num::NonZeroUsize};
+
+use crate::{
+    arrayvec::DetachedArrayVec,
+    node::{Children, NodeArray},
+    BTreeInner, DuplicatePolicy, NodeRef, OkBTree,
+};
+
+/// A description of a single node, as consumed by [`OkBTree::from_node_stream`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDesc<T> {
+    /// The distance from this node to the leaves. Leaves have height 0.
+    pub height: usize,
+    /// The elements stored in this node, in ascending order.
+    pub pivots: Vec<T>,
+}
+
+impl<T: Clone, const M: usize> NodeRef<'_, T, M> {
+    /// Copy this node into an owned description.
+    pub fn to_desc(&self) -> NodeDesc<T> {
+        NodeDesc {
+            height: self.height(),
+            pivots: self.pivots().to_vec(),
+        }
+    }
+}
+
+/// The reason a node stream could not be turned into a tree.
+///
+/// `node` is the position of the offending node in the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromNodeStreamError {
+    /// The stream ended while some internal node was still missing children.
+    UnexpectedEnd,
+    /// The tree was complete, but more nodes followed.
+    TrailingNodes { node: usize },
+    /// A node was not one level below its parent.
+    Height {
+        node: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A node held too few or too many pivots.
+    Fill { node: usize, len: usize },
+    /// A node's pivots were out of order, or fell outside of the range set by its ancestors.
+    Order { node: usize },
+    /// The root was taller than any tree that fits in memory.
+    TooTall { height: usize, max: usize },
+}
+
+impl fmt::Display for FromNodeStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromNodeStreamError::UnexpectedEnd => f.write_str("node stream ended early"),
+            FromNodeStreamError::TrailingNodes { node } => {
+                write!(f, "node {node} follows a complete tree")
+            }
+            FromNodeStreamError::Height {
+                node,
+                expected,
+                found,
+            } => write!(f, "node {node} has height {found}, expected {expected}"),
+            FromNodeStreamError::Fill { node, len } => {
+                write!(f, "node {node} has an invalid number of pivots ({len})")
+            }
+            FromNodeStreamError::Order { node } => write!(f, "node {node} is out of order"),
+            FromNodeStreamError::TooTall { height, max } => {
+                write!(
+                    f,
+                    "root has height {height}, but the tallest possible is {max}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromNodeStreamError {}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Rebuild a tree from a stream of node descriptions, in the order produced by
+    /// [`OkBTree::visit_nodes`]: depth-first, with each node before its children.
+    ///
+    /// Every node other than the root must hold between `M / 2` and `M` pivots, every
+    /// child must be one level below its parent, and the elements must be strictly
+    /// increasing in order. The stream is rejected if any of these do not hold.
+    pub fn from_node_stream<I>(nodes: I) -> Result<Self, FromNodeStreamError>
+    where
+        I: IntoIterator<Item = NodeDesc<T>>,
+    {
+        Self::from_node_stream_with_policy(nodes, DuplicatePolicy::Overwrite)
+    }
+
+    /// Rebuild a tree with the given [`DuplicatePolicy`] from a stream of node
+    /// descriptions, as in [`OkBTree::from_node_stream`].
+    ///
+    /// With [`DuplicatePolicy::AllowDuplicates`], equal elements may follow each other, so
+    /// a multiset round-trips through [`OkBTree::visit_nodes`]. Otherwise the elements
+    /// must be strictly increasing.
+    pub fn from_node_stream_with_policy<I>(
+        nodes: I,
+        policy: DuplicatePolicy,
+    ) -> Result<Self, FromNodeStreamError>
+    where
+        I: IntoIterator<Item = NodeDesc<T>>,
+    {
+        let mut stream = Stream {
+            nodes: nodes.into_iter(),
+            index: 0,
+            strict: policy != DuplicatePolicy::AllowDuplicates,
+        };
+
+        let Some(root) = stream.nodes.next() else {
+            return Ok(OkBTree::with_policy(policy));
+        };
+        let height = root.height;
+        // the stream is built recursively, one level per call, so bound the depth first.
+        let max = max_height::<M>();
+        if height > max {
+            return Err(FromNodeStreamError::TooTall { height, max });
+        }
+        let min = if height == 0 { 0 } else { 1 };
+        let node = stream.build(root, min, None, None)?;
+
+        if stream.nodes.next().is_some() {
+            return Err(FromNodeStreamError::TrailingNodes { node: stream.index });
+        }
+
+        let len = node.count;
+        let node = node.into_inner();
+        if node.len == 0 {
+            return Ok(OkBTree::with_policy(policy));
+        }
+
+        Ok(OkBTree(
+            Some(BTreeInner {
+                // a complete stream has a node at every height, so this cannot overflow.
+                depth: NonZeroUsize::new(height + 1).unwrap(),
+                len,
+                node,
+            }),
+            policy,
+        ))
+    }
+}
+
+/// The greatest height a tree can have.
+///
+/// A tree of height `h > 0` has at least `2 * (M / 2 + 1).pow(h - 1)` leaves, which are
+/// each a separate allocation, so there can be no more than `isize::MAX` of them.
+fn max_height<const M: usize>() -> usize {
+    let mut height = 0;
+    let mut leaves: usize = 1;
+    while let Some(next) = leaves.checked_mul(if height == 0 { 2 } else { M / 2 + 1 }) {
+        if next > isize::MAX as usize {
+            break;
+        }
+        leaves = next;
+        height += 1;
+    }
+    height
+}
+
+struct Stream<I> {
+    nodes: I,
+    index: usize,
+    /// Whether equal elements are rejected.
+    strict: bool,
+}
+
+impl<T: Ord, I: Iterator<Item = NodeDesc<T>>> Stream<I> {
+    fn in_order(&self, a: &T, b: &T) -> bool {
+        if self.strict {
+            a < b
+        } else {
+            a <= b
+        }
+    }
+
+    /// Build the subtree rooted at `desc`, whose elements must all lie between `lo` and `hi`.
+    fn build<const M: usize>(
+        &mut self,
+        desc: NodeDesc<T>,
+        min: usize,
+        lo: Option<&T>,
+        hi: Option<&T>,
+    ) -> Result<Subtree<T, M>, FromNodeStreamError> {
+        let node = self.index;
+        self.index += 1;
+
+        let NodeDesc { height, pivots } = desc;
+        let len = pivots.len();
+        if len < min || len > M {
+            return Err(FromNodeStreamError::Fill { node, len });
+        }
+
+        let sorted = pivots.windows(2).all(|w| self.in_order(&w[0], &w[1]));
+        let above = match (lo, pivots.first()) {
+            (Some(lo), Some(first)) => self.in_order(lo, first),
+            _ => true,
+        };
+        let below = match (pivots.last(), hi) {
+            (Some(last), Some(hi)) => self.in_order(last, hi),
+            _ => true,
+        };
+        if !(sorted && above && below) {
+            return Err(FromNodeStreamError::Order { node });
+        }
+
+        let mut count = len;
+        let mut children = Vec::new();
+        if height > 0 {
+            children.reserve(len + 1);
+            for index in 0..=len {
+                let child = self
+                    .nodes
+                    .next()
+                    .ok_or(FromNodeStreamError::UnexpectedEnd)?;
+                if child.height != height - 1 {
+                    return Err(FromNodeStreamError::Height {
+                        node: self.index,
+                        expected: height - 1,
+                        found: child.height,
+                    });
+                }
+
+                let lo = index.checked_sub(1).map(|i| &pivots[i]).or(lo);
+                let hi = pivots.get(index).or(hi);
+                let child = self.build(child, M / 2, lo, hi)?;
+                count += child.count;
+                children.push(child);
+            }
+        }
+
+        let mut array = NodeArray {
+            len,
+            pivots: DetachedArrayVec::new(),
+            children: Children::new(),
+        };
+
+        // SAFETY:
+        // pivots and children are currently uninit.
+        // len <= M so there is capacity for all of them.
+        unsafe {
+            for (i, pivot) in pivots.into_iter().enumerate() {
+                array.pivots.push(i, pivot);
+            }
+            let mut children = children.into_iter().map(Subtree::into_inner);
+            if let Some(head) = children.next() {
+                array.children.head.write(head);
+                for (i, child) in children.enumerate() {
+                    array.children.tail.push(i, child);
+                }
+            }
+        }
+
+        Ok(Subtree {
+            height,
+            count,
+            node: ManuallyDrop::new(Box::new(array)),
+        })
+    }
+}
+
+/// A fully built subtree, freed if the rest of the stream turns out to be invalid.
+struct Subtree<T, const M: usize> {
+    height: usize,
+    /// The number of elements in the subtree.
+    count: usize,
+    node: ManuallyDrop<Box<NodeArray<T, M>>>,
+}
+
+impl<T, const M: usize> Subtree<T, M> {
+    fn into_inner(self) -> Box<NodeArray<T, M>> {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: this is never used or dropped again.
+        unsafe { ManuallyDrop::take(&mut this.node) }
+    }
+}
+
+impl<T, const M: usize> Drop for Subtree<T, M> {
+    fn drop(&mut self) {
+        // SAFETY: height is correct. node is never used again.
+        unsafe {
+            self.node.drop_inner(self.height);
+            ManuallyDrop::drop(&mut self.node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FromNodeStreamError, NodeDesc};
+    use crate::{DuplicatePolicy, OkBTree};
+
+    fn leaf(pivots: &[i32]) -> NodeDesc<i32> {
+        NodeDesc {
+            height: 0,
+            pivots: pivots.to_vec(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut btree = OkBTree::new();
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+
+        let mut nodes = vec![];
+        btree.visit_nodes(|node| nodes.push(node.to_desc()));
+
+        let copy = OkBTree::<i32>::from_node_stream(nodes.clone()).unwrap();
+        assert_eq!(copy.len(), 1000);
+        let mut copied_nodes = vec![];
+        copy.visit_nodes(|node| copied_nodes.push(node.to_desc()));
+        assert_eq!(nodes, copied_nodes);
+
+        for i in 0..1000 {
+            assert_eq!(copy.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn round_trip_duplicates() {
+        let mut multiset = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..300 {
+            multiset.insert(i % 7);
+        }
+
+        let mut nodes = vec![];
+        multiset.visit_nodes(|node| nodes.push(node.to_desc()));
+        // equal elements are out of order for a set
+        assert!(matches!(
+            OkBTree::<i32, 2>::from_node_stream(nodes.clone()),
+            Err(FromNodeStreamError::Order { .. })
+        ));
+
+        let copy = OkBTree::<i32, 2>::from_node_stream_with_policy(
+            nodes,
+            DuplicatePolicy::AllowDuplicates,
+        )
+        .unwrap();
+        assert_eq!(copy.len(), 300);
+        assert_eq!(copy.policy(), DuplicatePolicy::AllowDuplicates);
+        assert_eq!(copy.validate(), Ok(()));
+        assert!(copy.iter().eq(multiset.iter()));
+    }
+
+    #[test]
+    fn invalid() {
+        let root = NodeDesc {
+            height: 1,
+            pivots: vec![10],
+        };
+
+        assert!(OkBTree::<i32>::from_node_stream([])
+            .unwrap()
+            .first()
+            .is_none());
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream([root.clone(), leaf(&[1, 2, 3, 4])]).err(),
+            Some(FromNodeStreamError::UnexpectedEnd)
+        );
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream([
+                root.clone(),
+                leaf(&[1, 2, 3, 4]),
+                leaf(&[11, 12, 13, 14]),
+                leaf(&[15, 16, 17, 18]),
+            ])
+            .err(),
+            Some(FromNodeStreamError::TrailingNodes { node: 3 })
+        );
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream([
+                root.clone(),
+                leaf(&[1, 2, 3]),
+                leaf(&[11, 12, 13, 14])
+            ])
+            .err(),
+            Some(FromNodeStreamError::Fill { node: 1, len: 3 })
+        );
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream([
+                root.clone(),
+                leaf(&[1, 2, 3, 4]),
+                leaf(&[9, 12, 13, 14])
+            ])
+            .err(),
+            Some(FromNodeStreamError::Order { node: 2 })
+        );
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream([root.clone(), root.clone(), leaf(&[11, 12, 13, 14])])
+                .err(),
+            Some(FromNodeStreamError::Height {
+                node: 1,
+                expected: 0,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn too_tall() {
+        assert_eq!(super::max_height::<2>(), 62);
+        assert_eq!(super::max_height::<8>(), 27);
+
+        // a chain of first children would otherwise recurse once per node before the stream
+        // runs out.
+        let chain = (0..=1_000_000usize).rev().map(|height| NodeDesc {
+            height,
+            pivots: vec![1, 2, 3, 4],
+        });
+        assert_eq!(
+            OkBTree::<i32>::from_node_stream(chain).err(),
+            Some(FromNodeStreamError::TooTall {
+                height: 1_000_000,
+                max: 27
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_drops_partial_tree() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let leaf = |n: usize| NodeDesc {
+            height: 0,
+            pivots: (0..4).map(|i| (n * 10 + i, value.clone())).collect(),
+        };
+        let root = NodeDesc {
+            height: 1,
+            pivots: vec![(5, value.clone()), (15, value.clone())],
+        };
+
+        // the last leaf is out of order, so the first two are dropped after being built.
+        let err = OkBTree::<_>::from_node_stream([root, leaf(0), leaf(1), leaf(0)]).err();
+        assert_eq!(err, Some(FromNodeStreamError::Order { node: 3 }));
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
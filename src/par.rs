@@ -0,0 +1,344 @@
+//! Rayon integration, available with the `rayon` feature.
+
+use std::{cmp::Ordering, mem, ops::RangeBounds, ptr};
+
+use equivalent::Comparable;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelExtend, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use crate::{node::InsertMode, DuplicatePolicy, NodeRef, OkBTree};
+
+impl<T: Ord + Send, const M: usize> ParallelExtend<T> for OkBTree<T, M> {
+    /// Collects and sorts the items in parallel, then merges them with the existing
+    /// elements in `O(n + m)`, rebuilding the tree along its right edge without searching.
+    ///
+    /// The sort is stable, so equal items are resolved as if they were inserted in the
+    /// order they were produced, and the tree's [`DuplicatePolicy`] decides which are kept.
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        let mut items: Vec<T> = par_iter.into_par_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+        items.par_sort();
+
+        // resolve equal items as inserting them in order would.
+        let policy = self.1;
+        match policy {
+            DuplicatePolicy::AllowDuplicates => {}
+            DuplicatePolicy::KeepExisting | DuplicatePolicy::Reject => items.dedup(),
+            DuplicatePolicy::Overwrite => items.dedup_by(|next, kept| {
+                let equal = next == kept;
+                if equal {
+                    mem::swap(next, kept);
+                }
+                equal
+            }),
+        }
+
+        let mut old = mem::replace(self, OkBTree::with_policy(policy))
+            .into_iter()
+            .peekable();
+        let mut new = items.into_iter().peekable();
+        loop {
+            let next = match (old.peek(), new.peek()) {
+                (Some(a), Some(b)) => match a.cmp(b) {
+                    Ordering::Less => old.next(),
+                    Ordering::Greater => new.next(),
+                    // an inserted duplicate goes after the existing ones.
+                    Ordering::Equal => match policy {
+                        DuplicatePolicy::AllowDuplicates => old.next(),
+                        DuplicatePolicy::KeepExisting | DuplicatePolicy::Reject => {
+                            new.next();
+                            old.next()
+                        }
+                        DuplicatePolicy::Overwrite => {
+                            old.next();
+                            new.next()
+                        }
+                    },
+                },
+                (Some(_), None) => old.next(),
+                (None, _) => new.next(),
+            };
+            let Some(next) = next else { break };
+            self.insert_inner(next, InsertMode::Append);
+        }
+    }
+}
+
+impl<T: Ord + Send, const M: usize> FromParallelIterator<T> for OkBTree<T, M> {
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        let mut tree = OkBTree::default();
+        tree.par_extend(par_iter);
+        tree
+    }
+}
+
+impl<T: Ord + Sync, const M: usize> OkBTree<T, M> {
+    /// A parallel iterator over the elements within `range`.
+    ///
+    /// Only the subtrees overlapping `range` are visited. Nodes don't track the size of their
+    /// subtrees, so the work is split along subtree boundaries instead. Every node other than
+    /// the root is at least half full, which keeps sibling subtrees of similar size.
+    pub fn par_range<Q, R>(&self, range: R) -> ParRange<'_, T, M>
+    where
+        Q: Comparable<T> + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let producer = match (&self.0, self.range(range).ends()) {
+            (Some(inner), Some((lo, hi))) => Some(RangeProducer {
+                parts: vec![Part::Node(NodeRef {
+                    height: inner.depth.get() - 1,
+                    node: &inner.node,
+                })],
+                lo,
+                hi,
+            }),
+            _ => None,
+        };
+        ParRange { producer }
+    }
+}
+
+/// A parallel iterator over the elements of an [`OkBTree`] within a range, from
+/// [`OkBTree::par_range`].
+pub struct ParRange<'a, T, const M: usize> {
+    producer: Option<RangeProducer<'a, T, M>>,
+}
+
+impl<'a, T: Ord + Sync, const M: usize> ParallelIterator for ParRange<'a, T, M> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        match self.producer {
+            Some(producer) => bridge_unindexed(producer, consumer),
+            None => consumer.into_folder().complete(),
+        }
+    }
+}
+
+/// A contiguous run of the tree, in order: whole subtrees and the pivots between them.
+enum Part<'a, T, const M: usize> {
+    Node(NodeRef<'a, T, M>),
+    Pivot(&'a T),
+}
+
+/// Splits the elements in `lo..=hi`, which are the first and last elements of the range by
+/// position in the tree. With duplicates, other copies of `lo` and `hi` can sit in the
+/// subtrees beside them, so the edges are found by identity rather than by value.
+struct RangeProducer<'a, T, const M: usize> {
+    parts: Vec<Part<'a, T, M>>,
+    lo: &'a T,
+    hi: &'a T,
+}
+
+impl<'a, T: Ord, const M: usize> RangeProducer<'a, T, M> {
+    /// Replace a node with its children and pivots, keeping only those that overlap the range.
+    fn expand(&self, node: NodeRef<'a, T, M>) -> Vec<Part<'a, T, M>> {
+        let pivots = node.pivots();
+        let start = pivots.partition_point(|p| p < self.lo);
+        let end = pivots.partition_point(|p| p <= self.hi);
+
+        if node.is_leaf() {
+            return pivots[start..end].iter().map(Part::Pivot).collect();
+        }
+        // the children at either end lie outside the range if it starts or ends at the
+        // pivot beside them.
+        let mut parts = vec![];
+        if !pivots.get(start).is_some_and(|p| ptr::eq(p, self.lo)) {
+            parts.push(Part::Node(node.child(start)));
+        }
+        for (index, pivot) in (start + 1..).zip(&pivots[start..end]) {
+            parts.push(Part::Pivot(pivot));
+            if index < end || !ptr::eq(pivot, self.hi) {
+                parts.push(Part::Node(node.child(index)));
+            }
+        }
+        parts
+    }
+}
+
+impl<'a, T: Ord + Sync, const M: usize> UnindexedProducer for RangeProducer<'a, T, M> {
+    type Item = &'a T;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        // descend until there is more than one part to hand out.
+        while let [Part::Node(node)] = self.parts[..] {
+            self.parts = self.expand(node);
+        }
+        if self.parts.len() < 2 {
+            return (self, None);
+        }
+        let right = RangeProducer {
+            parts: self.parts.split_off(self.parts.len() / 2),
+            lo: self.lo,
+            hi: self.hi,
+        };
+        (self, Some(right))
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, folder: F) -> F {
+        let mut folder = Some(folder);
+        let mut consume = |value| {
+            let f = folder.take().unwrap();
+            folder = Some(if f.full() { f } else { f.consume(value) });
+        };
+        for part in self.parts {
+            match part {
+                Part::Pivot(value) => consume(value),
+                Part::Node(node) => {
+                    node.node
+                        .for_each_between(node.height, self.lo, self.hi, &mut consume)
+                }
+            }
+        }
+        folder.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rayon::iter::{IntoParallelIterator, ParallelExtend, ParallelIterator};
+
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn par_collect() {
+        let mut btree: OkBTree<i32> = (0..1000).into_par_iter().map(|i| 999 - i).collect();
+        btree.par_extend((500..1500).into_par_iter());
+
+        for i in 0..1500 {
+            assert_eq!(btree.get(&i), Some(&i));
+        }
+        assert_eq!(btree.first(), Some(&0));
+        assert_eq!(btree.last(), Some(&1499));
+        btree.validate().unwrap();
+    }
+
+    #[test]
+    fn par_extend_policy() {
+        use std::cmp::Ordering;
+
+        // ordered by key alone, so equal keys can be told apart by tag.
+        #[derive(Debug, Clone, Copy)]
+        struct Tagged(u32, u32);
+        impl PartialEq for Tagged {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Tagged {}
+        impl PartialOrd for Tagged {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Tagged {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let items: Vec<Tagged> = (0..3000).map(|i| Tagged(i % 1000, i)).collect();
+        for policy in [
+            DuplicatePolicy::Overwrite,
+            DuplicatePolicy::KeepExisting,
+            DuplicatePolicy::Reject,
+            DuplicatePolicy::AllowDuplicates,
+        ] {
+            // inserting one at a time is the reference behaviour, for both an empty and a
+            // non-empty tree.
+            for seed in [0..0, 0..10, 500..1500] {
+                let mut expected = OkBTree::<Tagged, 2>::with_policy(policy);
+                expected.extend(seed.clone().step_by(7).map(|i| Tagged(i, u32::MAX)));
+                let mut found = expected.clone();
+                expected.extend(items.iter().copied());
+                found.par_extend(items.clone());
+
+                found.validate().unwrap();
+                let tags = |tree: &OkBTree<Tagged, 2>| tree.iter().map(|t| t.1).collect::<Vec<_>>();
+                assert_eq!(tags(&found), tags(&expected), "{policy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn par_range() {
+        let btree: OkBTree<i32, 2> = (0..10_000).into_par_iter().map(|i| i * 2).collect();
+
+        let window: Vec<&i32> = btree.par_range(1001..=5000).collect();
+        let expected: Vec<i32> = (501..=2500).map(|i| i * 2).collect();
+        assert_eq!(window.into_iter().copied().collect::<Vec<_>>(), expected);
+
+        assert_eq!(btree.par_range::<i32, _>(..).count(), 10_000);
+        assert_eq!(btree.par_range(19_998..).count(), 1);
+        assert_eq!(btree.par_range(5..5).count(), 0);
+        assert_eq!(OkBTree::<i32>::new().par_range::<i32, _>(..).count(), 0);
+
+        // copies of the ends can sit in the subtrees beside them
+        let mut multiset = OkBTree::<i32, 2>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in 0..20 {
+            multiset.insert(i);
+        }
+        for _ in 0..50 {
+            multiset.insert(5);
+            multiset.insert(9);
+        }
+        for range in [5..=5, 5..=9, 4..=9, 5..=12, 9..=9, 0..=19] {
+            let expected: Vec<i32> = multiset.range(range.clone()).copied().collect();
+            let mut found: Vec<i32> = multiset.par_range(range).copied().collect();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+        assert_eq!(multiset.par_range(5..=5).count(), 51);
+        assert_eq!(multiset.par_range(6..9).count(), 3);
+    }
+
+    #[test]
+    fn range_producer_split() {
+        use rayon::iter::plumbing::UnindexedProducer;
+
+        use super::RangeProducer;
+
+        // split as far as possible, collecting each piece in order.
+        fn collect<'a>(producer: RangeProducer<'a, i32, 2>, out: &mut Vec<Vec<i32>>) {
+            match producer.split() {
+                (left, Some(right)) => {
+                    collect(left, out);
+                    collect(right, out);
+                }
+                (piece, None) => {
+                    let mut values = vec![];
+                    for part in piece.parts {
+                        match part {
+                            super::Part::Pivot(v) => values.push(*v),
+                            super::Part::Node(node) => node.node.for_each_between(
+                                node.height,
+                                piece.lo,
+                                piece.hi,
+                                &mut |v| values.push(*v),
+                            ),
+                        }
+                    }
+                    out.push(values);
+                }
+            }
+        }
+
+        let btree: OkBTree<i32, 2> = (0..1000).into_par_iter().collect();
+        let mut pieces = vec![];
+        collect(btree.par_range(100..900).producer.unwrap(), &mut pieces);
+        assert!(pieces.iter().all(|piece| piece.len() == 1));
+        assert_eq!(pieces.concat(), (100..900).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{btree_map, BTreeMap},
+    iter::FusedIterator,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{DuplicatePolicy, OkBTree, Range};
+
+/// An ordered set split into child trees by bucket, such as an hour of timestamps, so that
+/// a whole bucket can be dropped at once.
+///
+/// `bucket` must be monotonic: if `a <= b` then `bucket(a) <= bucket(b)`. Each partition
+/// then holds a contiguous run of the elements, and iterating the partitions in bucket
+/// order visits the elements in order. It is a logic error for `bucket` not to be
+/// monotonic.
+///
+/// Dropping a partition removes its tree from the map of partitions, taking `O(log p)`
+/// time for `p` partitions, instead of removing each element as
+/// [`OkBTree::remove_range`] would. Empty partitions are removed as they empty.
+pub struct PartitionedBTree<B, T, F, const M: usize = 8> {
+    partitions: BTreeMap<B, OkBTree<T, M>>,
+    bucket: F,
+    policy: DuplicatePolicy,
+    len: usize,
+}
+
+impl<B: Ord, T: Ord, F: Fn(&T) -> B, const M: usize> PartitionedBTree<B, T, F, M> {
+    pub fn new(bucket: F) -> Self {
+        Self::with_policy(bucket, DuplicatePolicy::default())
+    }
+
+    /// Create an empty set whose partitions follow the given [`DuplicatePolicy`].
+    pub fn with_policy(bucket: F, policy: DuplicatePolicy) -> Self {
+        PartitionedBTree {
+            partitions: BTreeMap::new(),
+            bucket,
+            policy,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert `value` into its bucket's partition, as [`OkBTree::insert`] does.
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let policy = self.policy;
+        let tree = self
+            .partitions
+            .entry((self.bucket)(&value))
+            .or_insert_with(|| OkBTree::with_policy(policy));
+        let before = tree.len();
+        let old = tree.insert(value);
+        self.len = self.len + tree.len() - before;
+        old
+    }
+
+    /// Remove the element equal to `value`, as [`OkBTree::remove`] does.
+    pub fn remove(&mut self, value: &T) -> Option<T> {
+        let btree_map::Entry::Occupied(mut entry) = self.partitions.entry((self.bucket)(value))
+        else {
+            return None;
+        };
+        let removed = entry.get_mut().remove(value)?;
+        self.len -= 1;
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        Some(removed)
+    }
+
+    pub fn get(&self, value: &T) -> Option<&T> {
+        self.partitions.get(&(self.bucket)(value))?.get(value)
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.get(value).is_some()
+    }
+
+    /// The partition for `bucket`, if it holds any elements.
+    pub fn partition(&self, bucket: &B) -> Option<&OkBTree<T, M>> {
+        self.partitions.get(bucket)
+    }
+
+    /// Iterate over the non-empty partitions, in bucket order.
+    pub fn partitions(&self) -> btree_map::Iter<'_, B, OkBTree<T, M>> {
+        self.partitions.iter()
+    }
+
+    /// Remove the whole partition for `bucket`, returning its tree.
+    pub fn drop_partition(&mut self, bucket: &B) -> Option<OkBTree<T, M>> {
+        let tree = self.partitions.remove(bucket)?;
+        self.len -= tree.len();
+        Some(tree)
+    }
+
+    /// Remove every partition before `bucket`, returning the number of elements removed.
+    pub fn drop_partitions_before(&mut self, bucket: &B) -> usize {
+        let kept = self.partitions.split_off(bucket);
+        let dropped = std::mem::replace(&mut self.partitions, kept);
+        let removed = dropped.values().map(OkBTree::len).sum();
+        self.len -= removed;
+        removed
+    }
+
+    /// Iterate over the elements in ascending order.
+    pub fn iter(&self) -> PartitionedRange<'_, B, T, M> {
+        self.range(..)
+    }
+
+    /// Iterate over the elements within `range`, in ascending order.
+    ///
+    /// Only the partitions whose buckets overlap the range are visited. The partitions at
+    /// either end are searched for the bounds, and the ones between them are iterated
+    /// whole.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> PartitionedRange<'_, B, T, M> {
+        let bounds = (range.start_bound(), range.end_bound());
+        let lo = bounds.0.map(&self.bucket);
+        let hi = bounds.1.map(&self.bucket);
+        fn bucket<B>(bound: &Bound<B>) -> Option<&B> {
+            match bound {
+                Bound::Included(b) | Bound::Excluded(b) => Some(b),
+                Bound::Unbounded => None,
+            }
+        }
+        let edge = |b: Option<&B>| {
+            let tree = self.partitions.get(b?)?;
+            Some(tree.range::<T, _>(bounds))
+        };
+
+        match (bucket(&lo), bucket(&hi)) {
+            (Some(lo), Some(hi)) if lo >= hi => PartitionedRange {
+                front: if lo == hi { edge(Some(lo)) } else { None },
+                middle: self.partitions.range(lo..lo),
+                back: None,
+            },
+            (lo, hi) => PartitionedRange {
+                front: edge(lo),
+                middle: self.partitions.range::<B, _>((
+                    lo.map_or(Bound::Unbounded, Bound::Excluded),
+                    hi.map_or(Bound::Unbounded, Bound::Excluded),
+                )),
+                back: edge(hi),
+            },
+        }
+    }
+}
+
+impl<B: Ord, T: Ord, F: Fn(&T) -> B, const M: usize> Extend<T> for PartitionedBTree<B, T, F, M> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// An iterator over the elements of a [`PartitionedBTree`] within a range, from
+/// [`PartitionedBTree::range`].
+///
+/// It chains the [`Range`]s of the partitions it passes through.
+pub struct PartitionedRange<'a, B, T, const M: usize = 8> {
+    front: Option<Range<'a, T, M>>,
+    middle: btree_map::Range<'a, B, OkBTree<T, M>>,
+    back: Option<Range<'a, T, M>>,
+}
+
+impl<'a, B, T: Ord, const M: usize> Iterator for PartitionedRange<'a, B, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(value) = self.front.as_mut().and_then(Iterator::next) {
+                return Some(value);
+            }
+            match self.middle.next() {
+                Some((_, tree)) => self.front = Some(tree.range::<T, _>(..)),
+                None => return self.back.as_mut()?.next(),
+            }
+        }
+    }
+}
+
+impl<'a, B, T: Ord, const M: usize> DoubleEndedIterator for PartitionedRange<'a, B, T, M> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(value) = self.back.as_mut().and_then(DoubleEndedIterator::next_back) {
+                return Some(value);
+            }
+            match self.middle.next_back() {
+                Some((_, tree)) => self.back = Some(tree.range::<T, _>(..)),
+                None => return self.front.as_mut()?.next_back(),
+            }
+        }
+    }
+}
+
+impl<B, T: Ord, const M: usize> FusedIterator for PartitionedRange<'_, B, T, M> {}
+
+impl<B, T, const M: usize> Clone for PartitionedRange<'_, B, T, M> {
+    fn clone(&self) -> Self {
+        PartitionedRange {
+            front: self.front.clone(),
+            middle: self.middle.clone(),
+            back: self.back.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+
+    use super::PartitionedBTree;
+
+    #[test]
+    fn partitioned_range() {
+        // (timestamp, id), bucketed by the hundreds of the timestamp
+        let mut tree = PartitionedBTree::<_, _, _, 2>::new(|&(ts, _): &(u32, u32)| ts / 100);
+        let mut expected = BTreeSet::new();
+        for i in 0..1000 {
+            let value = ((i * 7919) % 1000, i);
+            tree.insert(value);
+            expected.insert(value);
+        }
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.partitions().count(), 10);
+        assert!(tree.iter().eq(expected.iter()));
+        assert!(tree.iter().rev().eq(expected.iter().rev()));
+
+        let bounds = [
+            Bound::Unbounded,
+            Bound::Included((0, 0)),
+            Bound::Included((150, 0)),
+            Bound::Excluded((199, u32::MAX)),
+            Bound::Included((200, 0)),
+            Bound::Excluded((550, 0)),
+            Bound::Included((999, u32::MAX)),
+            Bound::Included((5000, 0)),
+        ];
+        for lo in bounds {
+            for hi in bounds {
+                let range = (lo, hi);
+                let valid = match (lo, hi) {
+                    (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b))
+                    | (Bound::Included(a), Bound::Excluded(b)) => a <= b,
+                    (Bound::Excluded(a), Bound::Excluded(b)) => a < b,
+                    _ => true,
+                };
+                if !valid {
+                    // std panics on these, ours is empty
+                    assert_eq!(tree.range(range).count(), 0);
+                    continue;
+                }
+                assert!(tree.range(range).eq(expected.range(range)), "{range:?}");
+                assert!(
+                    tree.range(range).rev().eq(expected.range(range).rev()),
+                    "{range:?}"
+                );
+            }
+        }
+
+        // meeting in the middle from both ends
+        let mut range = tree.range((150, 0)..(450, 0));
+        let mut found = vec![];
+        while let (Some(a), Some(b)) = (range.next(), range.next_back()) {
+            found.push(*a);
+            found.push(*b);
+        }
+        found.extend(range.copied());
+        found.sort();
+        assert!(found.iter().eq(expected.range((150, 0)..(450, 0))));
+    }
+
+    #[test]
+    fn drop_partition() {
+        let mut tree = PartitionedBTree::<_, _, _, 2>::new(|&ts: &u32| ts / 10);
+        tree.extend(0..100);
+
+        assert_eq!(tree.drop_partition(&3).map(|p| p.len()), Some(10));
+        assert_eq!(tree.drop_partition(&3).map(|p| p.len()), None);
+        assert_eq!(tree.len(), 90);
+        assert!(!tree.contains(&35));
+        assert!(tree
+            .range(25..45)
+            .eq((25..30).chain(40..45).collect::<Vec<_>>().iter()));
+
+        assert_eq!(tree.drop_partitions_before(&5), 40);
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.iter().next(), Some(&50));
+
+        for i in 50..60 {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        assert_eq!(tree.remove(&50), None);
+        assert!(tree.partition(&5).is_none());
+        assert_eq!(tree.len(), 40);
+    }
+}
@@ -0,0 +1,100 @@
+//! Randomness for features that need it.
+//!
+//! Randomized features take their generator as an argument rather than seeding one
+//! themselves. With the `rand_core` feature, any [`rand_core::RngCore`] can be passed in,
+//! and [`SplitMix64`] implements it so that tests and simulations are reproducible from a
+//! single seed.
+
+/// A tiny, fast, seedable generator. Quality is plenty for picking operations and
+/// sampling elements, but it is not cryptographically secure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniform float in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        SplitMix64::next_u64(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for SplitMix64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: [u8; 8]) -> Self {
+        SplitMix64(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(state: u64) -> Self {
+        SplitMix64(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SplitMix64;
+
+    #[test]
+    fn reproducible() {
+        let mut a = SplitMix64::new(7);
+        let mut b = a.clone();
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+            assert!((0.0..1.0).contains(&a.next_f64()));
+            b.next_f64();
+        }
+        assert_ne!(SplitMix64::new(7).next_u64(), SplitMix64::new(8).next_u64());
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn rng_core() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let mut inherent = SplitMix64::new(7);
+        let mut rng = SplitMix64::seed_from_u64(7);
+        let mut bytes = [0; 12];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], inherent.next_u64().to_le_bytes());
+        assert_eq!(
+            bytes[8..],
+            ((inherent.next_u64() >> 32) as u32).to_le_bytes()
+        );
+        assert_eq!(
+            SplitMix64::from_seed(7u64.to_le_bytes()),
+            SplitMix64::new(7)
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+
+use equivalent::{Comparable, Equivalent};
+
+use crate::OkBTree;
+
+/// Mutations staged by [`OkBTree::transaction`].
+///
+/// Nothing is applied to the tree until the transaction closure returns `Ok`.
+pub struct Transaction<'q, T> {
+    ops: Vec<Staged<'q, T>>,
+}
+
+enum Staged<'q, T> {
+    Insert(T),
+    Remove(Probe<'q, T>),
+}
+
+/// A staged removal, comparing as the key it was staged with.
+struct Probe<'q, T>(Box<dyn Fn(&T) -> Ordering + 'q>);
+
+impl<T> Equivalent<T> for Probe<'_, T> {
+    fn equivalent(&self, key: &T) -> bool {
+        (self.0)(key).is_eq()
+    }
+}
+
+impl<T> Comparable<T> for Probe<'_, T> {
+    fn compare(&self, key: &T) -> Ordering {
+        (self.0)(key)
+    }
+}
+
+impl<'q, T> Transaction<'q, T> {
+    /// Stage an insertion of `value`.
+    pub fn insert(&mut self, value: T) {
+        self.ops.push(Staged::Insert(value));
+    }
+
+    /// Stage the removal of the element equal to `q`.
+    pub fn remove<Q: Comparable<T> + ?Sized>(&mut self, q: &'q Q) {
+        self.ops
+            .push(Staged::Remove(Probe(Box::new(move |key| q.compare(key)))));
+    }
+
+    /// The number of staged mutations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Stage a batch of mutations that are applied together, or not at all.
+    ///
+    /// `f` is given the tree as it was before the transaction, and a [`Transaction`] to
+    /// stage mutations into. Reads through the tree inside `f` don't see the mutations
+    /// staged so far. If `f` returns `Ok`, the staged mutations are applied in order.
+    /// If it returns `Err` or panics, they are discarded and the tree is left untouched.
+    ///
+    /// Applying the mutations is not atomic. If `T::cmp` panics partway through, the
+    /// mutations before it are kept and the rest are dropped.
+    pub fn transaction<'q, R, E>(
+        &mut self,
+        f: impl FnOnce(&Self, &mut Transaction<'q, T>) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let mut txn = Transaction { ops: Vec::new() };
+        let res = f(self, &mut txn)?;
+
+        for op in txn.ops {
+            match op {
+                Staged::Insert(value) => {
+                    self.insert(value);
+                }
+                Staged::Remove(probe) => {
+                    self.remove(&probe);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::OkBTree;
+
+    #[test]
+    fn transaction() {
+        let keys: Vec<usize> = (0..10).collect();
+        let mut btree = OkBTree::new();
+        for i in 0..10 {
+            btree.insert(i);
+        }
+
+        let moved = btree.transaction(|tree, txn| {
+            for i in &keys[..5] {
+                if tree.get(i).is_some() {
+                    txn.remove(i);
+                    txn.insert(i + 100);
+                }
+                // staged mutations aren't visible until the transaction is applied
+                assert_eq!(tree.get(&(i + 100)), None);
+            }
+            Ok::<_, ()>(txn.len() / 2)
+        });
+        assert_eq!(moved, Ok(5));
+        assert_eq!(btree.first(), Some(&5));
+        assert_eq!(btree.last(), Some(&104));
+
+        let err = btree.transaction(|_, txn| {
+            txn.remove(&5);
+            txn.insert(1000);
+            Err::<(), _>("rolled back")
+        });
+        assert_eq!(err, Err("rolled back"));
+        assert_eq!(btree.first(), Some(&5));
+        assert_eq!(btree.last(), Some(&104));
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            btree.transaction(|_, txn| -> Result<(), ()> {
+                txn.remove(&5);
+                panic!("abort")
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(btree.first(), Some(&5));
+    }
+
+    #[test]
+    fn transaction_borrowed_key() {
+        let mut btree: OkBTree<String> = ["a", "b", "c"].map(String::from).into_iter().collect();
+        let res = btree.transaction(|_, txn| {
+            txn.remove("b");
+            txn.insert("d".to_owned());
+            Ok::<_, ()>(())
+        });
+        assert_eq!(res, Ok(()));
+        assert!(btree.iter().eq(["a", "c", "d"]));
+    }
+}
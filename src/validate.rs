@@ -0,0 +1,324 @@
+use std::fmt;
+
+use equivalent::Comparable;
+
+use crate::{DuplicatePolicy, NodeRef, OkBTree};
+
+/// A broken invariant found by [`OkBTree::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptionError {
+    /// The index of the child taken at each level, from the root down to the offending node.
+    /// Empty for the root, or for problems with the tree as a whole.
+    pub path: Vec<usize>,
+    pub kind: Corruption,
+}
+
+/// What was wrong with the tree, from [`CorruptionError::kind`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Corruption {
+    /// A node held too few or too many pivots.
+    Fill {
+        min: usize,
+        max: usize,
+        found: usize,
+    },
+    /// The pivot at `index` was out of order with the pivot before it, or fell outside of
+    /// the range set by the node's ancestors. This is usually caused by an element whose
+    /// order changed while it was in the tree.
+    Order { index: usize },
+    /// The stored element count didn't match the number of elements in the nodes.
+    Len { expected: usize, found: usize },
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node at {:?} ", self.path)?;
+        match self.kind {
+            Corruption::Fill { min, max, found } => {
+                write!(f, "has {found} pivots, expected {min}..={max}")
+            }
+            Corruption::Order { index } => write!(f, "has pivot {index} out of order"),
+            Corruption::Len { expected, found } => {
+                write!(f, "holds {found} elements, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CorruptionError {}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Check the structure of the whole tree, returning the first broken invariant.
+    ///
+    /// Every node other than the root must hold between `M / 2` and `M` pivots, the
+    /// elements must be in order, and the element count must match. Elements are only
+    /// required to be strictly increasing if the policy is not
+    /// [`DuplicatePolicy::AllowDuplicates`].
+    ///
+    /// The tree's own operations never break these. A failure means an element's order
+    /// changed while it was in the tree, or memory was corrupted, and the tree should be
+    /// rebuilt from its elements. This walks every node, taking `O(n)` time.
+    pub fn validate(&self) -> Result<(), CorruptionError> {
+        let Some(inner) = &self.0 else { return Ok(()) };
+        let root = NodeRef {
+            height: inner.depth.get() - 1,
+            node: &inner.node,
+        };
+
+        let mut v = Validator {
+            strict: self.1 != DuplicatePolicy::AllowDuplicates,
+            path: Vec::with_capacity(root.height),
+        };
+        let min = if root.is_leaf() { 0 } else { 1 };
+        let found = v.check(root, min, None, None)?;
+        if found != inner.len {
+            return Err(CorruptionError {
+                path: vec![],
+                kind: Corruption::Len {
+                    expected: inner.len,
+                    found,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Insert `value` as [`OkBTree::insert`] does, after checking the nodes it can touch.
+    ///
+    /// Those are the nodes on the search path for `value`, along with their children,
+    /// which rebalancing borrows from or merges with. If one of them breaks an invariant
+    /// checked by [`OkBTree::validate`], the error is returned and the tree is left
+    /// unchanged, so it can be rebuilt. This costs `O(M log n)` extra comparisons. Nodes
+    /// off the path are not checked.
+    pub fn try_insert(&mut self, value: T) -> Result<Option<T>, CorruptionError> {
+        self.check_path(&value)?;
+        Ok(self.insert(value))
+    }
+
+    /// Remove the element equal to `q` as [`OkBTree::remove`] does, after checking the
+    /// nodes it can touch, as in [`OkBTree::try_insert`].
+    pub fn try_remove<Q>(&mut self, q: &Q) -> Result<Option<T>, CorruptionError>
+    where
+        Q: Comparable<T> + ?Sized,
+    {
+        self.check_path(q)?;
+        Ok(self.remove(q))
+    }
+
+    /// Check each node on the search path for `q`, and the fill of its children.
+    ///
+    /// An equal pivot in an internal node is passed on the left, which leads to the leaf
+    /// holding its predecessor, where a removal takes the replacement from.
+    fn check_path<Q>(&self, q: &Q) -> Result<(), CorruptionError>
+    where
+        Q: Comparable<T> + ?Sized,
+    {
+        let Some(inner) = &self.0 else { return Ok(()) };
+        let mut node = NodeRef {
+            height: inner.depth.get() - 1,
+            node: &inner.node,
+        };
+
+        let mut v = Validator {
+            strict: self.1 != DuplicatePolicy::AllowDuplicates,
+            path: Vec::with_capacity(node.height),
+        };
+        let mut min = if node.is_leaf() { 0 } else { 1 };
+        let (mut lo, mut hi) = (None, None);
+        loop {
+            v.check_node(node, min, lo, hi)?;
+            if node.is_leaf() {
+                return Ok(());
+            }
+
+            let pivots = node.pivots();
+            for index in 0..node.child_count() {
+                let len = node.child(index).pivots().len();
+                if len < M / 2 || len > M {
+                    v.path.push(index);
+                    return Err(v.error(Corruption::Fill {
+                        min: M / 2,
+                        max: M,
+                        found: len,
+                    }));
+                }
+            }
+
+            let index = pivots.partition_point(|p| q.compare(p).is_gt());
+            lo = index.checked_sub(1).map(|i| &pivots[i]).or(lo);
+            hi = pivots.get(index).or(hi);
+            min = M / 2;
+            v.path.push(index);
+            node = node.child(index);
+        }
+    }
+}
+
+struct Validator {
+    strict: bool,
+    path: Vec<usize>,
+}
+
+impl Validator {
+    fn in_order<T: Ord>(&self, a: &T, b: &T) -> bool {
+        if self.strict {
+            a < b
+        } else {
+            a <= b
+        }
+    }
+
+    fn error(&self, kind: Corruption) -> CorruptionError {
+        CorruptionError {
+            path: self.path.clone(),
+            kind,
+        }
+    }
+
+    /// Check the fill and order of `node` alone, whose elements must all lie between `lo`
+    /// and `hi`.
+    fn check_node<T: Ord, const M: usize>(
+        &self,
+        node: NodeRef<'_, T, M>,
+        min: usize,
+        lo: Option<&T>,
+        hi: Option<&T>,
+    ) -> Result<(), CorruptionError> {
+        let pivots = node.pivots();
+        if pivots.len() < min || pivots.len() > M {
+            return Err(self.error(Corruption::Fill {
+                min,
+                max: M,
+                found: pivots.len(),
+            }));
+        }
+
+        for (index, pivot) in pivots.iter().enumerate() {
+            let prev = index.checked_sub(1).map(|i| &pivots[i]).or(lo);
+            let after_prev = prev.map_or(true, |prev| self.in_order(prev, pivot));
+            let before_hi = hi.map_or(true, |hi| self.in_order(pivot, hi));
+            if !(after_prev && before_hi) {
+                return Err(self.error(Corruption::Order { index }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the subtree at `node`, whose elements must all lie between `lo` and `hi`,
+    /// returning how many elements it holds.
+    fn check<T: Ord, const M: usize>(
+        &mut self,
+        node: NodeRef<'_, T, M>,
+        min: usize,
+        lo: Option<&T>,
+        hi: Option<&T>,
+    ) -> Result<usize, CorruptionError> {
+        self.check_node(node, min, lo, hi)?;
+
+        let pivots = node.pivots();
+        let mut count = pivots.len();
+        for index in 0..node.child_count() {
+            let lo = index.checked_sub(1).map(|i| &pivots[i]).or(lo);
+            let hi = pivots.get(index).or(hi);
+            self.path.push(index);
+            count += self.check(node.child(index), M / 2, lo, hi)?;
+            self.path.pop();
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::{Corruption, CorruptionError};
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn validate() {
+        fn check<const M: usize>() {
+            let mut btree = OkBTree::<i32, M>::default();
+            assert_eq!(btree.validate(), Ok(()));
+            for i in 0..500 {
+                btree.insert((i * 7919) % 500);
+            }
+            for i in (0..500).step_by(3) {
+                btree.remove(&i);
+            }
+            assert_eq!(btree.validate(), Ok(()));
+
+            btree.0.as_mut().unwrap().len += 1;
+            assert_eq!(
+                btree.validate(),
+                Err(CorruptionError {
+                    path: vec![],
+                    kind: Corruption::Len {
+                        expected: 334,
+                        found: 333
+                    }
+                })
+            );
+
+            let mut multiset = OkBTree::<i32, M>::with_policy(DuplicatePolicy::AllowDuplicates);
+            for i in 0..100 {
+                multiset.insert(i % 3);
+            }
+            assert_eq!(multiset.validate(), Ok(()));
+        }
+
+        check::<2>();
+        check::<8>();
+    }
+
+    #[test]
+    fn validate_order() {
+        // an element whose order can change while it is in the tree
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Key(Cell<i32>);
+
+        let keys: Vec<Key> = (0..100).map(|i| Key(Cell::new(i))).collect();
+        let mut btree = OkBTree::<&Key, 2>::default();
+        for key in &keys {
+            btree.insert(key);
+        }
+        assert_eq!(btree.validate(), Ok(()));
+
+        keys[50].0.set(1000);
+        let err = btree.validate().unwrap_err();
+        assert!(matches!(err.kind, Corruption::Order { .. }));
+    }
+
+    #[test]
+    fn try_insert_remove() {
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Key(Cell<i32>);
+
+        let keys: Vec<Key> = (0..100).map(|i| Key(Cell::new(i * 2))).collect();
+        let extra = [Key(Cell::new(1)), Key(Cell::new(181))];
+        let mut btree = OkBTree::<&Key, 2>::default();
+        for key in &keys {
+            assert_eq!(btree.try_insert(key).map(|old| old.is_some()), Ok(false));
+        }
+        assert!(btree.try_remove(&&keys[10]).unwrap().is_some());
+        assert_eq!(
+            btree.try_remove(&&keys[10]).map(|old| old.is_some()),
+            Ok(false)
+        );
+
+        // an element near the end moves to the start, which searches beside it run into.
+        keys[90].0.set(-1);
+        let err = btree.try_insert(&extra[1]).err().unwrap();
+        assert!(matches!(err.kind, Corruption::Order { .. }));
+        assert_eq!(btree.len(), 99);
+        assert!(btree.try_remove(&&keys[91]).is_err());
+        assert_eq!(btree.len(), 99);
+
+        // paths that stay clear of it still work
+        assert_eq!(
+            btree.try_insert(&extra[0]).map(|old| old.is_some()),
+            Ok(false)
+        );
+        assert_eq!(btree.len(), 100);
+    }
+}
@@ -0,0 +1,248 @@
+use std::ops::{Deref, RangeBounds};
+
+use equivalent::Comparable;
+
+use crate::{node::InsertMode, DuplicatePolicy, Inserted, OkBTree};
+
+/// A change made to a [`Watched`] tree.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a, T> {
+    Inserted(&'a T),
+    Removed(&'a T),
+}
+
+impl<T> Clone for Event<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Event<'_, T> {}
+
+impl<'a, T> Event<'a, T> {
+    /// The element that was inserted or removed.
+    pub fn value(&self) -> &'a T {
+        match *self {
+            Event::Inserted(value) | Event::Removed(value) => value,
+        }
+    }
+}
+
+/// Identifies a watcher registered with [`Watched::watch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+type Notify<'a, T> = Box<dyn FnMut(Event<'_, T>) + 'a>;
+
+struct Watcher<'a, T> {
+    id: WatchId,
+    contains: Box<dyn Fn(&T) -> bool + 'a>,
+    notify: Notify<'a, T>,
+}
+
+/// An [`OkBTree`] that notifies registered watchers of changes within their key range.
+///
+/// Reads go straight to the underlying tree through `Deref`. Only the mutations provided
+/// here, `insert` and the `remove` family, are observed.
+pub struct Watched<'a, T, const M: usize = 8> {
+    tree: OkBTree<T, M>,
+    watchers: Vec<Watcher<'a, T>>,
+    next_id: u64,
+}
+
+impl<'a, T: Ord, const M: usize> Watched<'a, T, M> {
+    pub fn new(tree: OkBTree<T, M>) -> Self {
+        Watched {
+            tree,
+            watchers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Call `f` whenever an element within `range` is inserted or removed.
+    pub fn watch<R, F>(&mut self, range: R, f: F) -> WatchId
+    where
+        R: RangeBounds<T> + 'a,
+        F: FnMut(Event<'_, T>) + 'a,
+    {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        self.watchers.push(Watcher {
+            id,
+            contains: Box::new(move |value| range.contains(value)),
+            notify: Box::new(f),
+        });
+        id
+    }
+
+    /// Stop notifying the given watcher. Returns whether it was registered.
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        let len = self.watchers.len();
+        self.watchers.retain(|w| w.id != id);
+        self.watchers.len() != len
+    }
+
+    pub fn into_inner(self) -> OkBTree<T, M> {
+        self.tree
+    }
+
+    fn notify(watchers: &mut [Watcher<'a, T>], event: Event<'_, T>) {
+        for watcher in watchers {
+            if (watcher.contains)(event.value()) {
+                (watcher.notify)(event);
+            }
+        }
+    }
+
+    fn removed(&mut self, value: Option<T>) -> Option<T> {
+        if let Some(value) = &value {
+            Self::notify(&mut self.watchers, Event::Removed(value));
+        }
+        value
+    }
+
+    /// Insert `value` as [`OkBTree::insert`] does, notifying watchers of what changed.
+    ///
+    /// A replaced element is reported as removed before its replacement is reported as
+    /// inserted. An element that is kept out of the tree by the [`DuplicatePolicy`] is
+    /// not reported.
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let policy = self.tree.policy();
+        match self.tree.insert_inner(value, InsertMode::Search(policy)) {
+            Inserted::New(value) => {
+                Self::notify(&mut self.watchers, Event::Inserted(value));
+                None
+            }
+            Inserted::Replaced(value, old) => {
+                Self::notify(&mut self.watchers, Event::Removed(&old));
+                Self::notify(&mut self.watchers, Event::Inserted(value));
+                Some(old)
+            }
+            Inserted::Kept(_, value) => value.filter(|_| policy == DuplicatePolicy::Reject),
+        }
+    }
+
+    pub fn remove<Q: Comparable<T> + ?Sized>(&mut self, q: &Q) -> Option<T> {
+        let value = self.tree.remove(q);
+        self.removed(value)
+    }
+
+    pub fn remove_first(&mut self) -> Option<T> {
+        let value = self.tree.remove_first();
+        self.removed(value)
+    }
+
+    pub fn remove_last(&mut self) -> Option<T> {
+        let value = self.tree.remove_last();
+        self.removed(value)
+    }
+}
+
+impl<T, const M: usize> Deref for Watched<'_, T, M> {
+    type Target = OkBTree<T, M>;
+
+    fn deref(&self) -> &OkBTree<T, M> {
+        &self.tree
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::mpsc};
+
+    use super::{Event, Watched};
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn watch() {
+        let events = RefCell::new(vec![]);
+        let (tx, rx) = mpsc::channel();
+
+        let mut tree = Watched::new(OkBTree::new());
+        let a = tree.watch(10..20, |e| {
+            events
+                .borrow_mut()
+                .push((matches!(e, Event::Inserted(_)), *e.value()))
+        });
+        tree.watch(15.., move |e| tx.send(*e.value()).unwrap());
+
+        for i in 0..30 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.remove(&12), Some(12));
+        assert_eq!(tree.remove(&12), None);
+        assert_eq!(tree.remove_last(), Some(29));
+        assert!(tree.unwatch(a));
+        assert!(!tree.unwatch(a));
+        tree.remove(&11);
+
+        let mut expected: Vec<_> = (10..20).map(|i| (true, i)).collect();
+        expected.push((false, 12));
+        assert_eq!(*events.borrow(), expected);
+
+        let received: Vec<i32> = rx.try_iter().collect();
+        assert_eq!(received, (15..30).chain([29]).collect::<Vec<_>>());
+
+        assert_eq!(tree.first(), Some(&0));
+    }
+
+    #[test]
+    fn insert_policy() {
+        // compares on the first field only
+        #[derive(Debug)]
+        struct Entry(i32, char);
+        impl PartialEq for Entry {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Entry {}
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Entry {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        // the events seen and the values returned when inserting a, b and c as equal elements
+        fn run(policy: DuplicatePolicy) -> (String, Vec<Option<char>>) {
+            let events = RefCell::new(String::new());
+            let mut tree = Watched::new(OkBTree::<Entry, 2>::with_policy(policy));
+            tree.watch(.., |e| {
+                let kind = if matches!(e, Event::Inserted(_)) {
+                    '+'
+                } else {
+                    '-'
+                };
+                events.borrow_mut().extend([kind, e.value().1]);
+            });
+            let returned = ['a', 'b', 'c']
+                .into_iter()
+                .map(|c| tree.insert(Entry(1, c)).map(|e| e.1))
+                .collect();
+            drop(tree);
+            (events.into_inner(), returned)
+        }
+
+        assert_eq!(
+            run(DuplicatePolicy::Overwrite),
+            ("+a-a+b-b+c".into(), vec![None, Some('a'), Some('b')])
+        );
+        assert_eq!(
+            run(DuplicatePolicy::KeepExisting),
+            ("+a".into(), vec![None, None, None])
+        );
+        assert_eq!(
+            run(DuplicatePolicy::Reject),
+            ("+a".into(), vec![None, Some('b'), Some('c')])
+        );
+        assert_eq!(
+            run(DuplicatePolicy::AllowDuplicates),
+            ("+a+b+c".into(), vec![None, None, None])
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Reproducible operation mixes for measuring [`OkBTree`] performance.
+//!
+//! A [`Workload`] pairs a key distribution with a weighted mix of operations and a seed.
+//! The same workload always produces the same sequence of [`Op`]s, so runs can be compared
+//! across commits without the noise of a differing input.
+//!
+//! For fuzzing, [`first_divergence`] checks a sequence of operations against
+//! `std::collections::BTreeSet`, and [`shrink`] minimises a failing sequence into a short
+//! reproducer.
+
+use std::collections::BTreeSet;
+
+use crate::{OkBTree, SplitMix64};
+
+/// A single operation against an `OkBTree<u64>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Insert(u64),
+    Get(u64),
+    Remove(u64),
+    RemoveFirst,
+    RemoveLast,
+}
+
+impl Op {
+    /// Apply this operation to the tree, returning the value that was read or removed.
+    pub fn apply<const M: usize>(self, tree: &mut OkBTree<u64, M>) -> Option<u64> {
+        match self {
+            Op::Insert(key) => {
+                tree.insert(key);
+                None
+            }
+            Op::Get(key) => tree.get(&key).copied(),
+            Op::Remove(key) => tree.remove(&key),
+            Op::RemoveFirst => tree.remove_first(),
+            Op::RemoveLast => tree.remove_last(),
+        }
+    }
+
+    fn apply_model(self, model: &mut BTreeSet<u64>) -> Option<u64> {
+        match self {
+            Op::Insert(key) => {
+                model.insert(key);
+                None
+            }
+            Op::Get(key) => model.get(&key).copied(),
+            Op::Remove(key) => model.take(&key),
+            Op::RemoveFirst => model.pop_first(),
+            Op::RemoveLast => model.pop_last(),
+        }
+    }
+
+    fn key_mut(&mut self) -> Option<&mut u64> {
+        match self {
+            Op::Insert(key) | Op::Get(key) | Op::Remove(key) => Some(key),
+            Op::RemoveFirst | Op::RemoveLast => None,
+        }
+    }
+}
+
+/// Apply `ops` to both an `OkBTree` and a `std::collections::BTreeSet`, returning the index
+/// of the first operation whose result, or whose effect on the length, differs between them.
+pub fn first_divergence<const M: usize>(ops: &[Op]) -> Option<usize> {
+    let mut tree = OkBTree::<u64, M>::default();
+    let mut model = BTreeSet::new();
+    ops.iter().position(|op| {
+        op.apply(&mut tree) != op.apply_model(&mut model) || tree.len() != model.len()
+    })
+}
+
+/// Minimise a sequence of operations while keeping it failing.
+///
+/// `fails` should return `true` for `ops`. Operations are deleted, first in large chunks
+/// and then one at a time, and keys are shrunk towards zero, keeping only the changes for
+/// which `fails` still returns `true`. In the result, no single operation can be removed
+/// and no key can be made smaller by these steps.
+pub fn shrink(mut ops: Vec<Op>, mut fails: impl FnMut(&[Op]) -> bool) -> Vec<Op> {
+    loop {
+        let deleted = delete_ops(&mut ops, &mut fails);
+        let shrunk = shrink_keys(&mut ops, &mut fails);
+        if !deleted && !shrunk {
+            return ops;
+        }
+    }
+}
+
+fn delete_ops(ops: &mut Vec<Op>, fails: &mut impl FnMut(&[Op]) -> bool) -> bool {
+    let mut changed = false;
+    let mut chunk = ops.len() / 2;
+    while chunk > 0 {
+        let mut deleted = false;
+        let mut start = 0;
+        while start < ops.len() {
+            let end = (start + chunk).min(ops.len());
+            let candidate: Vec<Op> = ops[..start].iter().chain(&ops[end..]).copied().collect();
+            if fails(&candidate) {
+                *ops = candidate;
+                deleted = true;
+            } else {
+                start = end;
+            }
+        }
+
+        changed |= deleted;
+        if !deleted {
+            chunk /= 2;
+        }
+    }
+    changed
+}
+
+/// Shrink keys towards zero. A key is renamed everywhere it appears at once, so that the
+/// operations touching the same element stay paired up.
+fn shrink_keys(ops: &mut Vec<Op>, fails: &mut impl FnMut(&[Op]) -> bool) -> bool {
+    let mut keys: Vec<u64> = ops
+        .iter_mut()
+        .filter_map(|op| op.key_mut().copied())
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let rename = |ops: &[Op], from: u64, to: u64| -> Vec<Op> {
+        let mut ops = ops.to_vec();
+        for key in ops.iter_mut().filter_map(Op::key_mut) {
+            if *key == from {
+                *key = to;
+            }
+        }
+        ops
+    };
+
+    let mut changed = false;
+    for mut key in keys {
+        while let Some(candidate) = [0, key / 2, key.saturating_sub(1)]
+            .into_iter()
+            .filter(|&k| k < key)
+            .map(|k| (k, rename(ops, key, k)))
+            .find(|(_, candidate)| fails(candidate))
+        {
+            (key, *ops) = candidate;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// How keys are chosen for the keyed operations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keys {
+    /// Keys drawn uniformly from `0..n`.
+    Uniform { n: u64 },
+    /// Keys drawn from `0..n` with a zipfian skew towards the small keys.
+    ///
+    /// `theta` must be in `0.0..1.0`. Higher values are more skewed.
+    Zipfian { n: u64, theta: f64 },
+    /// Increasing keys starting at `start`, as in an append-only log.
+    Sequential { start: u64 },
+}
+
+/// Relative weights of each operation in a workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub insert: u32,
+    pub get: u32,
+    pub remove: u32,
+    pub remove_first: u32,
+    pub remove_last: u32,
+}
+
+impl Mix {
+    /// Mostly lookups with occasional inserts.
+    pub const READ_HEAVY: Mix = Mix {
+        insert: 5,
+        get: 95,
+        remove: 0,
+        remove_first: 0,
+        remove_last: 0,
+    };
+    /// Inserts and removes in equal measure, exercising splits and merges.
+    pub const WRITE_HEAVY: Mix = Mix {
+        insert: 50,
+        get: 0,
+        remove: 50,
+        remove_first: 0,
+        remove_last: 0,
+    };
+    /// Only inserts. Pair with [`Keys::Sequential`] for an append-only workload.
+    pub const APPEND: Mix = Mix {
+        insert: 1,
+        get: 0,
+        remove: 0,
+        remove_first: 0,
+        remove_last: 0,
+    };
+    /// Inserts balanced by removals from the front, like a priority queue.
+    pub const QUEUE: Mix = Mix {
+        insert: 1,
+        get: 0,
+        remove: 0,
+        remove_first: 1,
+        remove_last: 0,
+    };
+
+    fn total(&self) -> u64 {
+        self.insert as u64
+            + self.get as u64
+            + self.remove as u64
+            + self.remove_first as u64
+            + self.remove_last as u64
+    }
+}
+
+/// A seeded description of an operation stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Workload {
+    pub keys: Keys,
+    pub mix: Mix,
+    pub seed: u64,
+}
+
+impl Workload {
+    pub fn new(keys: Keys, mix: Mix, seed: u64) -> Self {
+        Workload { keys, mix, seed }
+    }
+
+    /// The infinite stream of operations described by this workload.
+    ///
+    /// # Panics
+    /// If every weight in the mix is zero, or the key distribution is empty.
+    pub fn ops(&self) -> Ops {
+        assert!(
+            self.mix.total() > 0,
+            "workload mix must have a non-zero weight"
+        );
+        let keys = match self.keys {
+            Keys::Uniform { n } => {
+                assert!(n > 0, "uniform key range must not be empty");
+                KeyGen::Uniform { n }
+            }
+            Keys::Zipfian { n, theta } => KeyGen::Zipfian(Zipfian::new(n, theta)),
+            Keys::Sequential { start } => KeyGen::Sequential { next: start },
+        };
+        Ops {
+            rng: SplitMix64::new(self.seed),
+            mix: self.mix,
+            keys,
+        }
+    }
+
+    /// Apply the first `n` operations of this workload to `tree`.
+    ///
+    /// Returns how many of the operations found a value.
+    pub fn run<const M: usize>(&self, tree: &mut OkBTree<u64, M>, n: usize) -> usize {
+        self.ops().take(n).filter_map(|op| op.apply(tree)).count()
+    }
+}
+
+/// Iterator over the operations of a [`Workload`].
+#[derive(Debug, Clone)]
+pub struct Ops {
+    rng: SplitMix64,
+    mix: Mix,
+    keys: KeyGen,
+}
+
+impl Iterator for Ops {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        let mut pick = self.rng.next_u64() % self.mix.total();
+
+        let weights = [
+            self.mix.insert,
+            self.mix.get,
+            self.mix.remove,
+            self.mix.remove_first,
+            self.mix.remove_last,
+        ];
+        let kind = weights.iter().position(|&w| {
+            if pick < w as u64 {
+                true
+            } else {
+                pick -= w as u64;
+                false
+            }
+        });
+
+        Some(match kind {
+            Some(0) => Op::Insert(self.keys.next(&mut self.rng)),
+            Some(1) => Op::Get(self.keys.next(&mut self.rng)),
+            Some(2) => Op::Remove(self.keys.next(&mut self.rng)),
+            Some(3) => Op::RemoveFirst,
+            _ => Op::RemoveLast,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum KeyGen {
+    Uniform { n: u64 },
+    Zipfian(Zipfian),
+    Sequential { next: u64 },
+}
+
+impl KeyGen {
+    fn next(&mut self, rng: &mut SplitMix64) -> u64 {
+        match self {
+            KeyGen::Uniform { n } => rng.next_u64() % *n,
+            KeyGen::Zipfian(z) => z.sample(rng),
+            KeyGen::Sequential { next } => {
+                let key = *next;
+                *next = next.wrapping_add(1);
+                key
+            }
+        }
+    }
+}
+
+/// The zipfian generator from Gray et al, "Quickly Generating Billion-Record Synthetic Databases",
+/// as popularised by YCSB.
+#[derive(Debug, Clone)]
+struct Zipfian {
+    n: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    fn new(n: u64, theta: f64) -> Self {
+        assert!(n > 0, "zipfian key range must not be empty");
+        assert!(
+            (0.0..1.0).contains(&theta),
+            "zipfian theta must be in 0.0..1.0"
+        );
+
+        let zeta = |n: u64| (1..=n).map(|i| (i as f64).powf(-theta)).sum::<f64>();
+        let zeta2 = zeta(2.min(n));
+        let zetan = zeta(n);
+
+        Zipfian {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+        }
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.n - 1);
+        }
+        let key = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+        (key as u64).min(self.n - 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{first_divergence, shrink, Keys, Mix, Op, Workload};
+    use crate::OkBTree;
+
+    #[test]
+    fn reproducible() {
+        let workload = Workload::new(Keys::Uniform { n: 100 }, Mix::WRITE_HEAVY, 42);
+        let a: Vec<Op> = workload.ops().take(1000).collect();
+        let b: Vec<Op> = workload.ops().take(1000).collect();
+        assert_eq!(a, b);
+
+        let other = Workload {
+            seed: 43,
+            ..workload
+        };
+        let c: Vec<Op> = other.ops().take(1000).collect();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn zipfian_skew() {
+        let workload = Workload::new(
+            Keys::Zipfian {
+                n: 1000,
+                theta: 0.99,
+            },
+            Mix::READ_HEAVY,
+            0,
+        );
+        let mut counts = vec![0usize; 1000];
+        for op in workload.ops().take(10_000) {
+            if let Op::Get(key) | Op::Insert(key) = op {
+                counts[key as usize] += 1;
+            }
+        }
+        assert!(counts[0] > counts[999]);
+        assert!(counts[..10].iter().sum::<usize>() > counts[990..].iter().sum::<usize>());
+    }
+
+    #[test]
+    fn append() {
+        let workload = Workload::new(Keys::Sequential { start: 10 }, Mix::APPEND, 0);
+        let mut tree = OkBTree::new();
+        workload.run(&mut tree, 100);
+        assert_eq!(tree.first(), Some(&10));
+        assert_eq!(tree.last(), Some(&109));
+    }
+
+    #[test]
+    fn model() {
+        let workload = Workload::new(Keys::Uniform { n: 500 }, Mix::WRITE_HEAVY, 7);
+        let ops: Vec<Op> = workload.ops().take(10_000).collect();
+        assert_eq!(first_divergence::<2>(&ops), None);
+        assert_eq!(first_divergence::<8>(&ops), None);
+    }
+
+    #[test]
+    fn shrink_to_reproducer() {
+        let workload = Workload::new(Keys::Uniform { n: 1000 }, Mix::WRITE_HEAVY, 1);
+        let mut ops: Vec<Op> = workload.ops().take(2000).collect();
+        ops.extend([Op::Insert(5), Op::Remove(5), Op::Insert(700), Op::Get(700)]);
+
+        // a pretend bug: finding 700 after removing something below 300
+        let fails = |ops: &[Op]| {
+            let mut tree = OkBTree::<u64>::new();
+            let mut removed_small = false;
+            ops.iter().any(|op| {
+                let res = op.apply(&mut tree);
+                if let Op::Remove(k) = op {
+                    removed_small |= *k < 300 && res.is_some();
+                }
+                removed_small && matches!(op, Op::Get(700)) && res.is_some()
+            })
+        };
+        assert!(fails(&ops));
+
+        let shrunk = shrink(ops, fails);
+        assert_eq!(
+            shrunk,
+            [Op::Insert(0), Op::Remove(0), Op::Insert(700), Op::Get(700)]
+        );
+    }
+
+    /// Call `f` with every sequence that inserts and then removes each of `0..n` exactly
+    /// once, in any interleaving.
+    #[cfg(feature = "exhaustive")]
+    fn interleavings(n: usize, f: &mut impl FnMut(&[Op])) {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Absent,
+            Inserted,
+            Removed,
+        }
+
+        fn go(ops: &mut Vec<Op>, states: &mut [State], f: &mut impl FnMut(&[Op])) {
+            if ops.len() == 2 * states.len() {
+                return f(ops);
+            }
+            for key in 0..states.len() {
+                let (op, next) = match states[key] {
+                    State::Absent => (Op::Insert(key as u64), State::Inserted),
+                    State::Inserted => (Op::Remove(key as u64), State::Removed),
+                    State::Removed => continue,
+                };
+                let prev = std::mem::replace(&mut states[key], next);
+                ops.push(op);
+                go(ops, states, f);
+                ops.pop();
+                states[key] = prev;
+            }
+        }
+
+        go(&mut vec![], &mut vec![State::Absent; n], f)
+    }
+
+    #[cfg(feature = "exhaustive")]
+    fn exhaustive<const M: usize>(n: usize) {
+        let mut count = 0;
+        interleavings(n, &mut |ops| {
+            count += 1;
+            if let Some(i) = first_divergence::<M>(ops) {
+                panic!("M={M}: {:?} diverged at op {i}", ops);
+            }
+        });
+        // (2n)! / 2^n
+        assert_eq!(count, (1..=2 * n).product::<usize>() >> n);
+    }
+
+    #[test]
+    #[cfg(feature = "exhaustive")]
+    fn exhaustive_interleavings() {
+        // six elements are enough to split the root and then collapse it again by
+        // underflowing either the head child or one of the tail children.
+        exhaustive::<2>(6);
+        exhaustive::<4>(6);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{cmp::Ordering, iter::FusedIterator, iter::Peekable};
+
+use crate::{Iter, OkBTree};
+
+/// An element from one or both sides of a [`ZipByKey`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EitherOrBoth<T> {
+    /// The element was only in the left tree.
+    Left(T),
+    /// The element was only in the right tree.
+    Right(T),
+    /// An equal element was in both trees.
+    Both(T, T),
+}
+
+impl<T> EitherOrBoth<T> {
+    /// The element from the left tree, if it had one.
+    pub fn left(self) -> Option<T> {
+        match self {
+            EitherOrBoth::Left(l) | EitherOrBoth::Both(l, _) => Some(l),
+            EitherOrBoth::Right(_) => None,
+        }
+    }
+
+    /// The element from the right tree, if it had one.
+    pub fn right(self) -> Option<T> {
+        match self {
+            EitherOrBoth::Right(r) | EitherOrBoth::Both(_, r) => Some(r),
+            EitherOrBoth::Left(_) => None,
+        }
+    }
+}
+
+/// An iterator aligning the elements of two trees in ascending order, from
+/// [`OkBTree::zip_by_key`].
+pub struct ZipByKey<'a, T, const M: usize = 8, const N: usize = 8> {
+    left: Peekable<Iter<'a, T, M>>,
+    right: Peekable<Iter<'a, T, N>>,
+}
+
+impl<T: Ord, const M: usize> OkBTree<T, M> {
+    /// Walk this tree and `other` together in ascending order, pairing up equal elements.
+    ///
+    /// Each element of either tree is yielded exactly once, so the union, intersection and
+    /// differences of the two trees are all filters over this iterator. If either tree
+    /// allows duplicates, equal elements are paired up in order and any extras are yielded
+    /// alone. This takes `O(n + m)` time.
+    pub fn zip_by_key<'a, const N: usize>(
+        &'a self,
+        other: &'a OkBTree<T, N>,
+    ) -> ZipByKey<'a, T, M, N> {
+        ZipByKey {
+            left: self.iter().peekable(),
+            right: other.iter().peekable(),
+        }
+    }
+}
+
+impl<'a, T: Ord, const M: usize, const N: usize> Iterator for ZipByKey<'a, T, M, N> {
+    type Item = EitherOrBoth<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ord = match (self.left.peek(), self.right.peek()) {
+            (Some(l), Some(r)) => l.cmp(r),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+        match ord {
+            Ordering::Less => self.left.next().map(EitherOrBoth::Left),
+            Ordering::Greater => self.right.next().map(EitherOrBoth::Right),
+            Ordering::Equal => Some(EitherOrBoth::Both(self.left.next()?, self.right.next()?)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.left.len();
+        let right = self.right.len();
+        (left.max(right), Some(left + right))
+    }
+}
+
+impl<T: Ord, const M: usize, const N: usize> FusedIterator for ZipByKey<'_, T, M, N> {}
+
+impl<T, const M: usize, const N: usize> Clone for ZipByKey<'_, T, M, N> {
+    fn clone(&self) -> Self {
+        ZipByKey {
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EitherOrBoth::{self, Both, Left, Right};
+    use crate::{DuplicatePolicy, OkBTree};
+
+    #[test]
+    fn zip_by_key() {
+        let evens: OkBTree<i32> = OkBTree::from_sorted_unchecked((0..100).step_by(2));
+        let threes: OkBTree<i32, 2> = OkBTree::from_sorted_unchecked((0..100).step_by(3));
+
+        let zipped: Vec<_> = evens.zip_by_key(&threes).collect();
+        assert_eq!(
+            zipped[..5],
+            [Both(&0, &0), Left(&2), Right(&3), Left(&4), Both(&6, &6)]
+        );
+        assert_eq!(
+            zipped.len(),
+            (0..100).filter(|i| i % 2 == 0 || i % 3 == 0).count()
+        );
+
+        let both: Vec<i32> = zipped
+            .iter()
+            .filter(|z| matches!(z, Both(..)))
+            .filter_map(|z| z.left().copied())
+            .collect();
+        assert_eq!(both, (0..100).step_by(6).collect::<Vec<_>>());
+        let left: Vec<i32> = zipped.iter().filter_map(|z| z.left().copied()).collect();
+        assert_eq!(left, evens.iter().copied().collect::<Vec<_>>());
+        let right: Vec<i32> = zipped.iter().filter_map(|z| z.right().copied()).collect();
+        assert_eq!(right, threes.iter().copied().collect::<Vec<_>>());
+
+        let empty = OkBTree::<i32>::new();
+        assert!(empty.zip_by_key(&threes).all(|z| matches!(z, Right(_))));
+        assert_eq!(empty.zip_by_key(&empty).next(), None::<EitherOrBoth<&i32>>);
+
+        // duplicates pair up in order, and any extras are alone
+        let mut a = OkBTree::<i32>::with_policy(DuplicatePolicy::AllowDuplicates);
+        let mut b = OkBTree::<i32>::with_policy(DuplicatePolicy::AllowDuplicates);
+        for i in [1, 1, 1] {
+            a.insert(i);
+        }
+        for i in [1, 2] {
+            b.insert(i);
+        }
+        assert_eq!(
+            a.zip_by_key(&b).collect::<Vec<_>>(),
+            [Both(&1, &1), Left(&1), Left(&1), Right(&2)]
+        );
+    }
+}